@@ -1,7 +1,8 @@
 #![feature(backtrace)]
-use peermaps_ingest::{Ingest,Key,EStore,LStore,Phase,EDB};
+use peermaps_ingest::{Ingest,Key,EStore,LStore,Phase,EDB,NodeCache,Coords,rebuild_backrefs,decompress,extract,filter,label,mapping,meta,migrate};
 use leveldb::{database::Database,options::Options};
 use async_std::{io,fs::File};
+use futures::io::AllowStdIo;
 
 type Error = Box<dyn std::error::Error+Send+Sync>;
 
@@ -21,7 +22,7 @@ async fn main() -> Result<(),Error> {
 
 async fn run() -> Result<(),Error> {
   let (args,argv) = argmap::new()
-    .booleans(&["help","h"])
+    .booleans(&["help","h","repair","tags","element-info","coastline"])
     .parse(std::env::args());
   if argv.contains_key("help") || argv.contains_key("h") {
     print!["{}", usage(&args)];
@@ -36,7 +37,7 @@ async fn run() -> Result<(),Error> {
   let start_time = std::time::Instant::now();
   let mut last_print = std::time::Instant::now();
   let mut last_phase: Option<Phase> = None;
-  let reporter = Box::new(move |phase: Phase, res| {
+  let mut reporter = Box::new(move |phase: Phase, res| {
     if let Err(e) = res {
       eprintln!["\x1b[1K\r[{}] {} error: {}",
         hms(start_time.elapsed().as_secs_f64() as u32), phase.to_string(), e];
@@ -76,13 +77,38 @@ async fn run() -> Result<(),Error> {
       let mut ingest = Ingest::new(
         LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
         EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
-      ).reporter(reporter);
-      let pbf_stream: Box<dyn std::io::Read+Send> = match pbf_file.as_str() {
-        "-" => Box::new(std::io::stdin()),
-        x => Box::new(std::fs::File::open(x)?),
-      };
-      ingest.load_pbf(pbf_stream).await?;
+      ).reporter(reporter).threads(get_threads(&argv)?)
+        .raw_tags(argv.contains_key("tags"))
+        .element_info(argv.contains_key("element-info"));
+      if let Some(version) = get_schema(&argv)? {
+        ingest = ingest.schema_version(version);
+      }
+      if let Some(region) = get_region(&argv)? {
+        ingest = ingest.region(region);
+      }
+      if let Some(filter) = get_filter(&argv)? {
+        ingest = ingest.filter(filter);
+      }
+      if let Some(mapping) = get_mapping(&argv)? {
+        ingest = ingest.mapping(mapping);
+      }
+      if let Some(policy) = get_label_policy(&argv)? {
+        ingest = ingest.label_policy(policy);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
+      let mut stream = open_input(pbf_file)?;
+      match get_format(&mut stream)? {
+        Format::Xml() => ingest.load_xml(stream).await?,
+        Format::O5m() => ingest.load_o5m(Box::new(AllowStdIo::new(stream))).await?,
+        Format::Pbf() => ingest.load_pbf(stream).await?,
+      }
       ingest.process().await;
+      if argv.contains_key("coastline") {
+        ingest.coastline().await?;
+      }
       eprint![""];
     },
     Some("pbf") => {
@@ -98,13 +124,109 @@ async fn run() -> Result<(),Error> {
       let mut ingest = Ingest::new(
         LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
         EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
-      ).reporter(reporter);
+      ).reporter(reporter).threads(get_threads(&argv)?)
+        .raw_tags(argv.contains_key("tags"))
+        .element_info(argv.contains_key("element-info"));
+      if let Some(version) = get_schema(&argv)? {
+        ingest = ingest.schema_version(version);
+      }
+      if let Some(region) = get_region(&argv)? {
+        ingest = ingest.region(region);
+      }
+      if let Some(filter) = get_filter(&argv)? {
+        ingest = ingest.filter(filter);
+      }
+      if let Some(mapping) = get_mapping(&argv)? {
+        ingest = ingest.mapping(mapping);
+      }
+      if let Some(policy) = get_label_policy(&argv)? {
+        ingest = ingest.label_policy(policy);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
       let pbf_stream: Box<dyn std::io::Read+Send> = match pbf_file.as_str() {
         "-" => Box::new(std::io::stdin()),
         x => Box::new(std::fs::File::open(x)?),
       };
       ingest.load_pbf(pbf_stream).await?;
       ingest.process().await;
+      if argv.contains_key("coastline") {
+        ingest.coastline().await?;
+      }
+      eprint![""];
+    },
+    Some("xml") => {
+      let stdin_file = "-".to_string();
+      let xml_file = argv.get("xml").or_else(|| argv.get("f"))
+        .and_then(|x| x.first())
+        .unwrap_or(&stdin_file);
+      let (ldb_dir, edb_dir) = get_dirs(&argv);
+      if ldb_dir.is_none() || edb_dir.is_none() {
+        eprint!["{}", usage(&args)];
+        std::process::exit(1);
+      }
+      let mut ingest = Ingest::new(
+        LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
+        EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
+      ).reporter(reporter).raw_tags(argv.contains_key("tags"))
+        .element_info(argv.contains_key("element-info"));
+      if let Some(version) = get_schema(&argv)? {
+        ingest = ingest.schema_version(version);
+      }
+      if let Some(filter) = get_filter(&argv)? {
+        ingest = ingest.filter(filter);
+      }
+      if let Some(mapping) = get_mapping(&argv)? {
+        ingest = ingest.mapping(mapping);
+      }
+      if let Some(policy) = get_label_policy(&argv)? {
+        ingest = ingest.label_policy(policy);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
+      ingest.load_xml(open_input(xml_file)?).await?;
+      eprint![""];
+    },
+    Some("o5m") => {
+      let stdin_file = "-".to_string();
+      let o5m_file = argv.get("o5m").or_else(|| argv.get("f"))
+        .and_then(|x| x.first())
+        .unwrap_or(&stdin_file);
+      let (ldb_dir, edb_dir) = get_dirs(&argv);
+      if ldb_dir.is_none() || edb_dir.is_none() {
+        eprint!["{}", usage(&args)];
+        std::process::exit(1);
+      }
+      let mut ingest = Ingest::new(
+        LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
+        EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
+      ).reporter(reporter).raw_tags(argv.contains_key("tags"))
+        .element_info(argv.contains_key("element-info"));
+      if let Some(version) = get_schema(&argv)? {
+        ingest = ingest.schema_version(version);
+      }
+      if let Some(filter) = get_filter(&argv)? {
+        ingest = ingest.filter(filter);
+      }
+      if let Some(mapping) = get_mapping(&argv)? {
+        ingest = ingest.mapping(mapping);
+      }
+      if let Some(policy) = get_label_policy(&argv)? {
+        ingest = ingest.label_policy(policy);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
+      let o5m_stream: Box<dyn io::Read+Unpin> = match o5m_file.as_str() {
+        "-" => Box::new(io::stdin()),
+        x => Box::new(File::open(x).await?),
+      };
+      ingest.load_o5m(o5m_stream).await?;
       eprint![""];
     },
     Some("process") => {
@@ -116,12 +238,52 @@ async fn run() -> Result<(),Error> {
       let mut ingest = Ingest::new(
         LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
         EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
-      ).reporter(reporter);
+      ).reporter(reporter).threads(get_threads(&argv)?);
+      if let Some(region) = get_region(&argv)? {
+        ingest = ingest.region(region);
+      }
+      if let Some(filter) = get_filter(&argv)? {
+        ingest = ingest.filter(filter);
+      }
+      if let Some(mapping) = get_mapping(&argv)? {
+        ingest = ingest.mapping(mapping);
+      }
+      if let Some(policy) = get_label_policy(&argv)? {
+        ingest = ingest.label_policy(policy);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
       ingest.process().await;
+      if argv.contains_key("coastline") {
+        ingest.coastline().await?;
+      }
+      eprint![""];
+    },
+    Some("coastline") => {
+      let (ldb_dir, edb_dir) = get_dirs(&argv);
+      if ldb_dir.is_none() || edb_dir.is_none() {
+        eprint!["{}", usage(&args)];
+        std::process::exit(1);
+      }
+      let mut ingest = Ingest::new(
+        LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
+        EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
+      ).reporter(reporter);
+      if let Some(region) = get_region(&argv)? {
+        ingest = ingest.region(region);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
+      ingest.coastline().await?;
       eprint![""];
     },
     Some("changeset") => {
-      let o5c_file = argv.get("o5c").or_else(|| argv.get("f"))
+      let o5c_file = argv.get("o5c").or_else(|| argv.get("osc"))
+        .or_else(|| argv.get("f"))
         .and_then(|x| x.first());
       let (ldb_dir, edb_dir) = get_dirs(&argv);
       if o5c_file.is_none() || ldb_dir.is_none() || edb_dir.is_none() {
@@ -131,12 +293,141 @@ async fn run() -> Result<(),Error> {
       let mut ingest = Ingest::new(
         LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
         EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
-      ).reporter(reporter);
-      let o5c_stream: Box<dyn io::Read+Unpin> = match o5c_file.unwrap().as_str() {
-        "-" => Box::new(io::stdin()),
-        x => Box::new(File::open(x).await?),
+      ).reporter(reporter).raw_tags(argv.contains_key("tags"))
+        .element_info(argv.contains_key("element-info"));
+      if let Some(region) = get_region(&argv)? {
+        ingest = ingest.region(region);
+      }
+      if let Some(filter) = get_filter(&argv)? {
+        ingest = ingest.filter(filter);
+      }
+      if let Some(mapping) = get_mapping(&argv)? {
+        ingest = ingest.mapping(mapping);
+      }
+      if let Some(policy) = get_label_policy(&argv)? {
+        ingest = ingest.label_policy(policy);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
+      let mut stream = open_input(o5c_file.unwrap())?;
+      match get_format(&mut stream)? {
+        Format::Xml() => ingest.changeset_osc(stream).await?,
+        _ => ingest.changeset(Box::new(AllowStdIo::new(stream))).await?,
+      }
+      eprint![""];
+    },
+    Some("replicate") => {
+      let repl_dir = argv.get("dir").or_else(|| argv.get("d"))
+        .and_then(|x| x.first());
+      let sequence = match argv.get("sequence").or_else(|| argv.get("s"))
+        .and_then(|x| x.first()) {
+        Some(s) => Some(s.parse::<u64>()?),
+        None => None,
+      };
+      let (ldb_dir, edb_dir) = get_dirs(&argv);
+      if repl_dir.is_none() || ldb_dir.is_none() || edb_dir.is_none() {
+        eprint!["{}",usage(&args)];
+        std::process::exit(1);
+      }
+      let mut ingest = Ingest::new(
+        LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
+        EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
+      ).reporter(reporter).raw_tags(argv.contains_key("tags"))
+        .element_info(argv.contains_key("element-info"));
+      if let Some(region) = get_region(&argv)? {
+        ingest = ingest.region(region);
+      }
+      if let Some(filter) = get_filter(&argv)? {
+        ingest = ingest.filter(filter);
+      }
+      if let Some(mapping) = get_mapping(&argv)? {
+        ingest = ingest.mapping(mapping);
+      }
+      if let Some(policy) = get_label_policy(&argv)? {
+        ingest = ingest.label_policy(policy);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
+      let seq = ingest.replicate(std::path::Path::new(repl_dir.unwrap()), sequence).await?;
+      eprint![""];
+      println!["{}", seq];
+    },
+    Some("info") => {
+      let (ldb_dir, _) = get_dirs(&argv);
+      if ldb_dir.is_none() {
+        eprint!["{}",usage(&args)];
+        std::process::exit(1);
+      }
+      let mut lstore = LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?);
+      for (name,value) in meta::list(&mut lstore)?.iter() {
+        println!["{}: {}", name, meta::format_value(name, value)?];
+      }
+    },
+    Some("migrate") => {
+      let (ldb_dir, _) = get_dirs(&argv);
+      if ldb_dir.is_none() {
+        eprint!["{}",usage(&args)];
+        std::process::exit(1);
+      }
+      let mut lstore = LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?);
+      let to = match get_schema(&argv)? {
+        Some(version) => version,
+        None => migrate::schema_version(&mut lstore)?.unwrap_or(0).max(meta::SCHEMA_VERSION),
       };
-      ingest.changeset(o5c_stream).await?;
+      let version = migrate::migrate(&mut lstore, to, || reporter(Phase::Migrate(), Ok(())))?;
+      eprint![""];
+      println!["{}", version];
+    },
+    Some("verify") => {
+      let (ldb_dir, edb_dir) = get_dirs(&argv);
+      if ldb_dir.is_none() || edb_dir.is_none() {
+        eprint!["{}",usage(&args)];
+        std::process::exit(1);
+      }
+      let repair = argv.contains_key("repair");
+      let mut ingest = Ingest::new(
+        LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?),
+        EStore::new(open_eyros(&std::path::Path::new(&edb_dir.unwrap())).await?)
+      ).reporter(reporter);
+      if let Some(region) = get_region(&argv)? {
+        ingest = ingest.region(region);
+      }
+      if let Some(filter) = get_filter(&argv)? {
+        ingest = ingest.filter(filter);
+      }
+      if let Some(mapping) = get_mapping(&argv)? {
+        ingest = ingest.mapping(mapping);
+      }
+      if let Some(policy) = get_label_policy(&argv)? {
+        ingest = ingest.label_policy(policy);
+      }
+      ingest.check_schema().await?;
+      if let Some(nodes) = get_node_cache(&argv)? {
+        ingest = ingest.node_cache(nodes);
+      }
+      let problems = ingest.verify(repair).await?;
+      eprint![""];
+      for p in problems.iter() {
+        println!["{}", p];
+      }
+      if !problems.is_empty() && !repair {
+        std::process::exit(1);
+      }
+    },
+    Some("rebuild-backrefs") => {
+      let (ldb_dir, _) = get_dirs(&argv);
+      if ldb_dir.is_none() {
+        eprint!["{}",usage(&args)];
+        std::process::exit(1);
+      }
+      let mut lstore = LStore::new(open(std::path::Path::new(&ldb_dir.unwrap()))?);
+      let coords = Coords::from_schema_version(migrate::check(&mut lstore, None)?);
+      let nodes = get_node_cache(&argv)?;
+      rebuild_backrefs(&mut lstore, nodes.as_ref(), coords, || reporter(Phase::Backrefs(), Ok(())))?;
       eprint![""];
     },
     Some(cmd) => {
@@ -154,6 +445,27 @@ fn open(path: &std::path::Path) -> Result<Database<Key>,Error> {
   Database::open(path, options).map_err(|e| e.into())
 }
 
+// inputs are decompressed here so that get_format() can see the real content
+fn open_input(file: &str) -> Result<Box<dyn std::io::BufRead+Send>,Error> {
+  match file {
+    "-" => decompress(std::io::BufReader::new(std::io::stdin())),
+    x => decompress(std::io::BufReader::new(std::fs::File::open(x)?)),
+  }
+}
+
+enum Format { Pbf(), O5m(), Xml() }
+
+// osm xml starts with "<" after any leading whitespace, o5m starts with a 0xff
+// reset byte, and pbf starts with the big-endian length of the first blob header
+fn get_format(stream: &mut dyn std::io::BufRead) -> Result<Format,Error> {
+  let buf = stream.fill_buf()?;
+  Ok(match buf.iter().find(|c| !c.is_ascii_whitespace()) {
+    Some(b'<') => Format::Xml(),
+    _ if buf.first() == Some(&0xff) => Format::O5m(),
+    _ => Format::Pbf(),
+  })
+}
+
 async fn open_eyros(file: &std::path::Path) -> Result<EDB,Error> {
   eyros::Setup::from_path(&std::path::Path::new(&file))
     .build().await
@@ -162,28 +474,174 @@ async fn open_eyros(file: &std::path::Path) -> Result<EDB,Error> {
 fn usage(args: &[String]) -> String {
   format![indoc::indoc![r#"usage: {} COMMAND {{OPTIONS}}
 
-    ingest - runs pbf and process phases
-      -f, --pbf     osm pbf file to ingest or "-" for stdin (default)
+    ingest - runs pbf (or xml or o5m) and process phases
+      -f, --pbf     osm pbf, xml, or o5m file to ingest or "-" for stdin (default)
       -l, --ldb     level db dir to write normalized data
       -e, --edb     eyros db dir to write spatial data
       -o, --outdir  write level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes   dense node location cache file
+      -t, --threads number of worker threads (default: one per cpu)
+      --schema      schema version of a new level db (default: 1)
+      --bbox        only keep data in minlon,minlat,maxlon,maxlat
+      --poly        only keep data in a .poly or geojson polygon file
+      --filter      tag filter like w/highway or !n/amenity=bench (repeatable)
+      --filter-file file of tag filters, one per line
+      --types       file of tag patterns to feature types on top of georender's
+      --lang        label languages to keep in order like en,de,name (default: all)
+      --lang-fallback  label languages to try in order when none of --lang are present
+      --max-labels  keep at most this many labels
+      --normalize   unicode normalization of labels: nfc or nfkc
+      --tags        keep raw tags so process can re-run with a new tag mapping
+      --element-info  keep element versions so stale changes are skipped
+      --coastline   run the coastline phase after process
 
     pbf - parse pbf and write normalized data to level db
       -f, --pbf     osm pbf file to ingest or "-" for stdin (default)
       -l, --ldb     level db dir to write normalized data
       -e, --edb     eyros db dir to write spatial data
       -o, --outdir  write level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes   dense node location cache file
+      -t, --threads number of worker threads (default: one per cpu)
+      --schema      schema version of a new level db (default: 1)
+      --bbox        only keep data in minlon,minlat,maxlon,maxlat
+      --poly        only keep data in a .poly or geojson polygon file
+      --filter      tag filter like w/highway or !n/amenity=bench (repeatable)
+      --filter-file file of tag filters, one per line
+      --types       file of tag patterns to feature types on top of georender's
+      --lang        label languages to keep in order like en,de,name (default: all)
+      --lang-fallback  label languages to try in order when none of --lang are present
+      --max-labels  keep at most this many labels
+      --normalize   unicode normalization of labels: nfc or nfkc
+      --tags        keep raw tags so process can re-run with a new tag mapping
+      --element-info  keep element versions so stale changes are skipped
+      --coastline   run the coastline phase after process
+
+    xml - parse osm xml and write normalized data to level db
+      -f, --xml     osm xml file to ingest or "-" for stdin (default)
+      -l, --ldb     level db dir to write normalized data
+      -e, --edb     eyros db dir to write spatial data
+      -o, --outdir  write level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes   dense node location cache file
+      --schema      schema version of a new level db (default: 1)
+      --filter      tag filter like w/highway or !n/amenity=bench (repeatable)
+      --filter-file file of tag filters, one per line
+      --types       file of tag patterns to feature types on top of georender's
+      --lang        label languages to keep in order like en,de,name (default: all)
+      --lang-fallback  label languages to try in order when none of --lang are present
+      --max-labels  keep at most this many labels
+      --normalize   unicode normalization of labels: nfc or nfkc
+      --tags        keep raw tags so process can re-run with a new tag mapping
+      --element-info  keep element versions so stale changes are skipped
+
+    o5m - parse o5m and write normalized data to level db
+      -f, --o5m     o5m file to ingest or "-" for stdin (default)
+      -l, --ldb     level db dir to write normalized data
+      -e, --edb     eyros db dir to write spatial data
+      -o, --outdir  write level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes   dense node location cache file
+      --schema      schema version of a new level db (default: 1)
+      --filter      tag filter like w/highway or !n/amenity=bench (repeatable)
+      --filter-file file of tag filters, one per line
+      --types       file of tag patterns to feature types on top of georender's
+      --lang        label languages to keep in order like en,de,name (default: all)
+      --lang-fallback  label languages to try in order when none of --lang are present
+      --max-labels  keep at most this many labels
+      --normalize   unicode normalization of labels: nfc or nfkc
+      --tags        keep raw tags so process can re-run with a new tag mapping
+      --element-info  keep element versions so stale changes are skipped
 
     process - write georender-pack data to eyros db from populated level db
       -l, --ldb     level db dir to write normalized data
       -e, --edb     eyros db dir to write spatial data
       -o, --outdir  write level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes   dense node location cache file
+      -t, --threads number of worker threads (default: one per cpu)
+      --bbox        bbox of an extract, to leave out points outside of it
+      --poly        polygon file of an extract, to leave out points outside of it
+      --filter      tag filter like w/highway or !n/amenity=bench (repeatable)
+      --filter-file file of tag filters, applied again to raw tags
+      --types       file of tag patterns to feature types on top of georender's
+      --lang        label languages to keep in order like en,de,name (default: all)
+      --lang-fallback  label languages to try in order when none of --lang are present
+      --max-labels  keep at most this many labels
+      --normalize   unicode normalization of labels: nfc or nfkc
+      --coastline   run the coastline phase after process
 
-    changeset - ingest data from an o5c changeset
-      -f, --o5c     o5c changeset file or "-" for stdin (default)
+    coastline - write land and water areas assembled from natural=coastline ways
+      -l, --ldb     level db dir to read coastline ways from
+      -e, --edb     eyros db dir to write the areas to
+      -o, --outdir  use the level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes   dense node location cache file
+      --bbox        bounds to close open coastlines against
+                    (default: the bbox of the extract or the pbf header)
+      --poly        close open coastlines against the bbox of a polygon file
+
+    changeset - ingest data from an o5c or osmChange (osc) changeset
+      -f, --o5c     o5c or osc changeset file or "-" for stdin (default)
       -l, --ldb     level db dir to write normalized data
       -e, --edb     eyros db dir to write spatial data
       -o, --outdir  write level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes   dense node location cache file
+      --bbox        only apply changes in minlon,minlat,maxlon,maxlat
+      --poly        only apply changes in a .poly or geojson polygon file
+      --filter      tag filter like w/highway or !n/amenity=bench (repeatable)
+      --filter-file file of tag filters, one per line
+      --types       file of tag patterns to feature types on top of georender's
+      --lang        label languages to keep in order like en,de,name (default: all)
+      --lang-fallback  label languages to try in order when none of --lang are present
+      --max-labels  keep at most this many labels
+      --normalize   unicode normalization of labels: nfc or nfkc
+      --tags        keep raw tags so process can re-run with a new tag mapping
+      --element-info  keep element versions so stale changes are skipped
+
+    replicate - apply newer diffs from a local replication directory
+      -d, --dir       replication dir with state.txt and 000/000/001.osc.gz files
+      -s, --sequence  apply diffs after this sequence instead of the stored one
+      -l, --ldb       level db dir to write normalized data
+      -e, --edb       eyros db dir to write spatial data
+      -o, --outdir    write level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes     dense node location cache file
+      --bbox          only apply changes in minlon,minlat,maxlon,maxlat
+      --poly          only apply changes in a .poly or geojson polygon file
+      --filter        tag filter like w/highway or !n/amenity=bench (repeatable)
+      --filter-file   file of tag filters, one per line
+      --types         file of tag patterns to feature types on top of georender's
+      --lang          label languages to keep in order like en,de,name (default: all)
+      --lang-fallback label languages to try in order when none of --lang are present
+      --max-labels    keep at most this many labels
+      --normalize     unicode normalization of labels: nfc or nfkc
+      --tags          keep raw tags so process can re-run with a new tag mapping
+      --element-info  keep element versions so stale changes are skipped
+
+    info - print metadata about what was ingested into a level db
+      -l, --ldb     level db dir to read metadata from
+      -o, --outdir  read the level db in this dir in ldb/
+
+    migrate - rewrite level db records into another schema version
+      -l, --ldb     level db dir to migrate
+      -o, --outdir  migrate the level db in this dir in ldb/
+      --schema      schema version to migrate to (default: 1 or the current version)
+
+    verify - check that the eyros db and backrefs match the level db records
+      --repair      fix the problems that were found
+      -l, --ldb     level db dir to check
+      -e, --edb     eyros db dir to check
+      -o, --outdir  check the level and eyros db in this dir in ldb/ and edb/
+      -n, --nodes   dense node location cache file
+      --bbox        bbox of an extract, to leave out points outside of it
+      --poly        polygon file of an extract, to leave out points outside of it
+      --filter      tag filter that process ran with (repeatable)
+      --filter-file file of tag filters that process ran with
+      --types       type mapping file that the level db was loaded with
+      --lang        label languages that process ran with
+      --lang-fallback  label fallback languages that process ran with
+      --max-labels  label limit that process ran with
+      --normalize   label normalization that process ran with
+
+    rebuild-backrefs - regenerate all backrefs from the level db records
+      -l, --ldb     level db dir to rebuild backrefs in
+      -o, --outdir  use the level db in this dir in ldb/
+      -n, --nodes   dense node location cache file
 
     -h, --help     Print this help message
     -v, --version  Print the version string ({})
@@ -216,6 +674,72 @@ fn get_dirs(argv: &argmap::Map) -> (Option<String>,Option<String>) {
   (ldb_dir,edb_dir)
 }
 
+fn get_node_cache(argv: &argmap::Map) -> Result<Option<NodeCache>,Error> {
+  match argv.get("nodes").or_else(|| argv.get("n")).and_then(|x| x.first()) {
+    Some(file) => Ok(Some(NodeCache::open(std::path::Path::new(file))?)),
+    None => Ok(None),
+  }
+}
+
+fn get_threads(argv: &argmap::Map) -> Result<usize,Error> {
+  Ok(match argv.get("threads").or_else(|| argv.get("t")).and_then(|x| x.first()) {
+    Some(n) => n.parse()?,
+    None => 0,
+  })
+}
+
+fn get_schema(argv: &argmap::Map) -> Result<Option<u64>,Error> {
+  Ok(match argv.get("schema").and_then(|x| x.first()) {
+    Some(n) => Some(n.parse()?),
+    None => None,
+  })
+}
+
+fn get_filter(argv: &argmap::Map) -> Result<Option<filter::Filter>,Error> {
+  let mut filter = match argv.get("filter-file").and_then(|x| x.first()) {
+    Some(file) => filter::Filter::from_path(std::path::Path::new(file))?,
+    None => filter::Filter::new(),
+  };
+  for expr in argv.get("filter").into_iter().flatten() {
+    filter.push(expr)?;
+  }
+  Ok(if filter.is_empty() { None } else { Some(filter) })
+}
+
+fn get_label_policy(argv: &argmap::Map) -> Result<Option<label::LabelPolicy>,Error> {
+  let mut policy = label::LabelPolicy::new();
+  if let Some(langs) = argv.get("lang").and_then(|x| x.first()) {
+    policy.languages = label::LabelPolicy::parse_languages(langs)?;
+  }
+  if let Some(langs) = argv.get("lang-fallback").and_then(|x| x.first()) {
+    policy.fallback = label::LabelPolicy::parse_languages(langs)?;
+  }
+  if let Some(n) = argv.get("max-labels").and_then(|x| x.first()) {
+    policy.max_labels = Some(n.parse()?);
+  }
+  if let Some(form) = argv.get("normalize").and_then(|x| x.first()) {
+    policy.normalization = Some(label::Normalization::from_name(form)?);
+  }
+  Ok(if policy.is_empty() { None } else { Some(policy) })
+}
+
+fn get_mapping(argv: &argmap::Map) -> Result<Option<mapping::Mapping>,Error> {
+  Ok(match argv.get("types").and_then(|x| x.first()) {
+    Some(file) => Some(mapping::Mapping::from_path(std::path::Path::new(file))?),
+    None => None,
+  })
+}
+
+fn get_region(argv: &argmap::Map) -> Result<Option<extract::Region>,Error> {
+  if let Some(file) = argv.get("poly").and_then(|x| x.first()) {
+    return Ok(Some(extract::Region::from_path(std::path::Path::new(file))?));
+  }
+  Ok(match argv.get("bbox").and_then(|x| x.first()) {
+    Some(b) => Some(extract::Region::Bbox(extract::parse_bbox(b)?)),
+    None => None,
+  })
+}
+
 fn hms(t: u32) -> String {
   let s = t % 60;
   let m = (t / 60) % 60;
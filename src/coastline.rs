@@ -0,0 +1,378 @@
+use std::collections::{HashMap,HashSet};
+use crate::{Ingest,Phase,Error,Key,Decoded,decode,write_header,meta,
+  extract::{self,BBox,Ring},error::IngestErrorKind as E};
+
+type P = (eyros::Coord<f32>,eyros::Coord<f32>);
+
+// coastline rows are relations with ids from here up, far past the ids that
+// osm hands out, so they never collide with the row of a record
+pub const FIRST_ID: u64 = 1<<50;
+
+pub fn is_coastline_id(ex_id: u64) -> bool {
+  ex_id%3 == 2 && ex_id/3 >= FIRST_ID
+}
+
+// a land or water polygon. rings are closed (first == last)
+#[derive(Debug,Clone,PartialEq)]
+pub struct Area {
+  pub land: bool,
+  pub outer: Ring,
+  pub holes: Vec<Ring>,
+}
+
+// join coastline ways (node ids in way order) end to start into rings. land
+// is on the left of a coastline, so closed rings are land. with a bbox, the
+// parts of the coastline inside of it are closed into land rings by following
+// the bbox counterclockwise from the end of each part to the next start, and
+// into water rings the same way with the parts reversed. closed rings inside
+// of the bbox are islands, which are holes in the water around them. without a
+// bbox, only closed rings are kept. coastlines that don't close and can't be
+// closed against the bbox are returned as problems and left out, as are nodes
+// without a (finite) position, which are skipped
+pub fn assemble(ways: &[Vec<u64>], nodes: &HashMap<u64,(f64,f64)>, bbox: Option<&BBox>)
+-> (Vec<Area>,Vec<Error>) {
+  let mut problems = vec![];
+  let mut islands = vec![];
+  let mut parts = vec![];
+  let mut covered = false;
+  for chain in chains(ways) {
+    let (first,last) = (chain[0],chain[chain.len()-1]);
+    let mut ps = Vec::with_capacity(chain.len());
+    for r in chain.iter() {
+      match nodes.get(r) {
+        Some((x,y)) if x.is_finite() && y.is_finite() => ps.push((*x,*y)),
+        _ => problems.push(invalid(format!["node {} has no position", r])),
+      }
+    }
+    if first == last && ps.len() < 4 { continue }
+    let bbox = match bbox {
+      Some(b) => b,
+      None if first == last => {
+        islands.push(ps);
+        continue;
+      },
+      None => {
+        problems.push(unclosed(first, last));
+        continue;
+      },
+    };
+    if first == last {
+      // rings that leave the bbox are clipped like open coastlines,
+      // starting from a node outside of it
+      let k = match ps.iter().position(|(x,y)| !extract::contains(bbox, *x, *y)) {
+        Some(k) => k,
+        None => {
+          islands.push(ps);
+          continue;
+        },
+      };
+      let mut ring = ps[k..ps.len()-1].to_vec();
+      ring.extend_from_slice(&ps[..=k]);
+      let clipped = clip(&ring, bbox);
+      if clipped.is_empty() {
+        let center = ((bbox.0+bbox.2)/2.0,(bbox.1+bbox.3)/2.0);
+        covered = covered || extract::Polygon::new(ring, vec![]).contains(center.0, center.1);
+      }
+      parts.extend(clipped);
+      continue;
+    }
+    let clipped = clip(&ps, bbox);
+    if clipped.iter().all(|p| on_boundary(bbox, p[0]) && on_boundary(bbox, p[p.len()-1])) {
+      parts.extend(clipped);
+    } else {
+      problems.push(unclosed(first, last));
+    }
+  }
+
+  let mut areas = islands.iter().map(|ring| {
+    Area { land: true, outer: ring.clone(), holes: vec![] }
+  }).collect::<Vec<Area>>();
+  let bbox = match bbox {
+    Some(b) => b,
+    None => return (areas,problems),
+  };
+  let (w,s,e,n) = *bbox;
+  let whole = vec![(w,s),(e,s),(e,n),(w,n),(w,s)];
+  let (land,water) = match (parts.is_empty(),covered) {
+    (true,true) => (vec![whole],vec![]),
+    (true,false) => (vec![],vec![whole]),
+    (false,_) => {
+      let reversed = parts.iter().map(|p| p.iter().rev().copied().collect()).collect::<Vec<Ring>>();
+      (walk(&parts, bbox), walk(&reversed, bbox))
+    },
+  };
+  areas.extend(land.into_iter().map(|ring| Area { land: true, outer: ring, holes: vec![] }));
+  for ring in water {
+    let polygon = extract::Polygon::new(ring.clone(), vec![]);
+    let holes = islands.iter().filter(|island| polygon.contains(island[0].0, island[0].1))
+      .cloned().collect();
+    areas.push(Area { land: false, outer: ring, holes });
+  }
+  (areas,problems)
+}
+
+fn unclosed(first: u64, last: u64) -> Error {
+  invalid(format!["not closed between nodes {} and {}", first, last])
+}
+
+fn invalid(message: String) -> Error {
+  let res: Result<(),_> = E::InvalidCoastline { message }.raise();
+  res.unwrap_err().into()
+}
+
+// join ways whose last node is the first node of another way. ways that no
+// other way leads into start a chain first, so only rings are left after
+fn chains(ways: &[Vec<u64>]) -> Vec<Vec<u64>> {
+  let ways = ways.iter().filter(|w| w.len() > 1).collect::<Vec<_>>();
+  let mut starts: HashMap<u64,Vec<usize>> = HashMap::new();
+  for (i,w) in ways.iter().enumerate() {
+    starts.entry(w[0]).or_default().push(i);
+  }
+  let ends = ways.iter().map(|w| w[w.len()-1]).collect::<HashSet<u64>>();
+  let heads = (0..ways.len()).filter(|i| !ends.contains(&ways[*i][0])).chain(0..ways.len());
+  let mut used = vec![false;ways.len()];
+  let mut chains = vec![];
+  for i in heads.collect::<Vec<usize>>() {
+    if used[i] { continue }
+    used[i] = true;
+    let mut chain = ways[i].clone();
+    while chain[0] != chain[chain.len()-1] {
+      let end = chain[chain.len()-1];
+      match starts.get(&end).and_then(|js| js.iter().find(|j| !used[**j])) {
+        Some(j) => {
+          used[*j] = true;
+          chain.extend_from_slice(&ways[*j][1..]);
+        },
+        None => break,
+      }
+    }
+    chains.push(chain);
+  }
+  chains
+}
+
+// close parts that start and end on the bbox into rings. from the end of each
+// part, the bbox is followed counterclockwise to the nearest start of a part,
+// which keeps the left side of the parts inside of the rings
+fn walk(parts: &[Ring], bbox: &BBox) -> Vec<Ring> {
+  let starts = parts.iter().map(|p| perimeter(bbox, p[0])).collect::<Vec<f64>>();
+  let mut used = vec![false;parts.len()];
+  let mut rings = vec![];
+  for i in 0..parts.len() {
+    if used[i] { continue }
+    let mut ring: Ring = vec![];
+    let mut j = i;
+    while !used[j] {
+      used[j] = true;
+      for p in parts[j].iter() {
+        if ring.last() != Some(p) { ring.push(*p) }
+      }
+      let t = perimeter(bbox, parts[j][parts[j].len()-1]);
+      let d = |k: usize| (starts[k] - t).rem_euclid(4.0);
+      j = (0..parts.len()).filter(|k| !used[*k] || *k == i)
+        .min_by(|a,b| d(*a).total_cmp(&d(*b)))
+        .unwrap();
+      for p in corners(bbox, t, d(j)) {
+        if ring.last() != Some(&p) { ring.push(p) }
+      }
+    }
+    if ring.last() != ring.first() { ring.push(ring[0]) }
+    if ring.len() >= 4 { rings.push(ring) }
+  }
+  rings
+}
+
+// distance along the bbox counterclockwise from its south west corner,
+// one unit per side
+fn perimeter(bbox: &BBox, (x,y): (f64,f64)) -> f64 {
+  let (w,s,e,n) = *bbox;
+  let fx = if e > w { ((x-w)/(e-w)).max(0.0).min(1.0) } else { 0.0 };
+  let fy = if n > s { ((y-s)/(n-s)).max(0.0).min(1.0) } else { 0.0 };
+  let ds = [(y-s).abs(), (x-e).abs(), (y-n).abs(), (x-w).abs()];
+  let side = (0..4).min_by(|a,b| ds[*a].total_cmp(&ds[*b])).unwrap();
+  match side {
+    0 => fx,
+    1 => 1.0 + fy,
+    2 => 3.0 - fx,
+    _ => (4.0 - fy) % 4.0,
+  }
+}
+
+// the bbox corners passed going counterclockwise for d from t
+fn corners(bbox: &BBox, t: f64, d: f64) -> Vec<(f64,f64)> {
+  let (w,s,e,n) = *bbox;
+  let points = [(w,s),(e,s),(e,n),(w,n)];
+  (1..=4).map(|k| t.floor() + k as f64)
+    .filter(|c| *c < t + d)
+    .map(|c| points[(c as usize) % 4])
+    .collect()
+}
+
+fn on_boundary(bbox: &BBox, (x,y): (f64,f64)) -> bool {
+  let (w,s,e,n) = *bbox;
+  let eps = 1e-9;
+  (x-w).abs() < eps || (x-e).abs() < eps || (y-s).abs() < eps || (y-n).abs() < eps
+}
+
+// the parts of a line inside of the bbox
+fn clip(ps: &[(f64,f64)], bbox: &BBox) -> Vec<Ring> {
+  let mut parts = vec![];
+  let mut part: Ring = vec![];
+  for seg in ps.windows(2) {
+    match clip_segment(seg[0], seg[1], bbox) {
+      Some((a,b)) => {
+        if part.last() != Some(&a) {
+          if !part.is_empty() { parts.push(std::mem::take(&mut part)) }
+          part.push(a);
+        }
+        if b != a { part.push(b) }
+        // the segment leaves the bbox
+        if b != seg[1] { parts.push(std::mem::take(&mut part)) }
+      },
+      None if !part.is_empty() => parts.push(std::mem::take(&mut part)),
+      None => {},
+    }
+  }
+  if !part.is_empty() { parts.push(part) }
+  parts.retain(|p| p.len() > 1);
+  parts
+}
+
+// liang-barsky
+fn clip_segment(p: (f64,f64), q: (f64,f64), bbox: &BBox) -> Option<((f64,f64),(f64,f64))> {
+  let (w,s,e,n) = *bbox;
+  let (dx,dy) = (q.0-p.0, q.1-p.1);
+  let (mut t0, mut t1) = (0.0f64, 1.0f64);
+  for (pk,qk) in [(-dx,p.0-w),(dx,e-p.0),(-dy,p.1-s),(dy,n-p.1)].iter() {
+    if *pk == 0.0 {
+      if *qk < 0.0 { return None }
+      continue;
+    }
+    let r = qk/pk;
+    if *pk < 0.0 { t0 = t0.max(r) } else { t1 = t1.min(r) }
+    if t0 > t1 { return None }
+  }
+  let at = |t: f64| match t {
+    t if t == 0.0 => p,
+    t if t == 1.0 => q,
+    t => ((p.0+t*dx).max(w).min(e), (p.1+t*dy).max(s).min(n)),
+  };
+  Some((at(t0),at(t1)))
+}
+
+impl Ingest {
+  // gather the natural.coastline ways, assemble them into land and water
+  // areas (see assemble), and write those into eyros in place of the rows of
+  // the last run. open coastlines are closed against the region of the ingest,
+  // or else the bbox of the extract or the pbf header. coastlines that can't be
+  // assembled are reported, and changesets don't update the areas, so run
+  // this again after applying them
+  pub async fn coastline(&mut self) -> Result<(),Error> {
+    let db = {
+      let mut lstore = self.lstore.lock().await;
+      lstore.flush()?;
+      lstore.db.clone()
+    };
+    let coords = self.coords();
+    let coastline = *georender_pack::osm_types::get_types().get("natural.coastline").unwrap();
+    let mut ways = vec![];
+    for (key,value) in extract::records(&db, 1) {
+      if let Decoded::Way(way) = decode(&key.data, &value, coords)? {
+        if way.feature_type == coastline { ways.push(way.refs) }
+      }
+    }
+    let mut deps = HashMap::new();
+    self.get_way_deps(ways.iter().flatten(), &mut deps).await?;
+    let nodes: HashMap<u64,(f64,f64)> = deps.iter().map(|(r,(lon,lat))| (*r,(*lon as f64,*lat as f64))).collect();
+    let bbox = match &self.region {
+      Some(region) => Some(region.bbox()),
+      None => match self.get_meta("extract_bbox").await? {
+        Some(buf) => Some(meta::decode_bbox(&buf)?),
+        None => match self.get_meta("bbox").await? {
+          Some(buf) => Some(meta::decode_bbox(&buf)?),
+          None => None,
+        },
+      },
+    };
+    let (areas,problems) = assemble(&ways, &nodes, bbox.as_ref());
+    if let Some(f) = self.reporter.lock().await.as_mut() {
+      for e in problems { f(Phase::Coastline(), Err(e)) }
+    }
+
+    // rows of the last run are deleted first so that the new ones don't land in the same batch
+    if let Some(buf) = self.get_meta("coastline_rows").await? {
+      let mut estore = self.estore.lock().await;
+      for (i,chunk) in buf.chunks(32).enumerate() {
+        let (w,s,e,n) = meta::decode_bbox(chunk)?;
+        let point = (
+          eyros::Coord::Interval(w as f32,e as f32),
+          eyros::Coord::Interval(s as f32,n as f32),
+        );
+        estore.push_delete(point, (FIRST_ID + i as u64)*3+2);
+      }
+      estore.flush().await?;
+    }
+    // land gets its own type so that renderers can tell it from the coastline
+    // lines, which keep natural.coastline
+    let land = *georender_pack::osm_types::get_types().get("place.island").unwrap();
+    let water = *georender_pack::osm_types::get_types().get("place.ocean").unwrap();
+    let mut rows = vec![];
+    for area in areas.iter() {
+      let id = (FIRST_ID + (rows.len()/32) as u64)*3+2;
+      if let Some((point,encoded)) = area_row(id, area, if area.land { land } else { water })? {
+        if let (eyros::Coord::Interval(w,e),eyros::Coord::Interval(s,n)) = &point {
+          rows.extend(meta::encode_bbox(&(*w as f64,*s as f64,*e as f64,*n as f64))?);
+        }
+        let mut estore = self.estore.lock().await;
+        estore.create(point, encoded.into()).await?;
+      }
+      if let Some(f) = self.reporter.lock().await.as_mut() {
+        f(Phase::Coastline(), Ok(()));
+      }
+    }
+    {
+      let mut estore = self.estore.lock().await;
+      estore.flush().await?;
+      estore.sync().await?;
+    }
+    let mut lstore = self.lstore.lock().await;
+    lstore.put(Key::from(&crate::meta_key("coastline_rows")), &rows)?;
+    write_header(&mut lstore, &Phase::Coastline(), self.get_schema_version())?;
+    lstore.flush()?;
+    Ok(())
+  }
+}
+
+// georender gets the rings as closed ways of fresh nodes, outer first
+fn area_row(id: u64, area: &Area, feature_type: u64) -> Result<Option<(P,Vec<u8>)>,Error> {
+  let mut nodes = HashMap::new();
+  let mut ways = HashMap::new();
+  let mut members = vec![];
+  for (i,ring) in std::iter::once(&area.outer).chain(area.holes.iter()).enumerate() {
+    let mut refs = vec![];
+    for (lon,lat) in ring[..ring.len()-1].iter() {
+      refs.push(nodes.len() as u64);
+      nodes.insert(nodes.len() as u64, (*lon as f32,*lat as f32));
+    }
+    refs.push(refs[0]);
+    ways.insert(i as u64, refs);
+    members.push(georender_pack::Member::new(
+      i as u64,
+      match i {
+        0 => georender_pack::MemberRole::Outer(),
+        _ => georender_pack::MemberRole::Inner(),
+      },
+      georender_pack::MemberType::Way()
+    ));
+  }
+  let encoded = georender_pack::encode::relation_from_parsed(
+    id, feature_type, true, &[0], &members, &nodes, &ways
+  )?;
+  let lines = std::iter::once(&area.outer).chain(area.holes.iter()).map(|ring| {
+    ring.iter().map(|(lon,lat)| (*lon as f32,*lat as f32)).collect()
+  }).collect::<Vec<_>>();
+  match crate::bbox_point(&lines) {
+    Some(point) if !encoded.is_empty() => Ok(Some((point,encoded))),
+    _ => Ok(None),
+  }
+}
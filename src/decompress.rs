@@ -0,0 +1,68 @@
+use std::io::{self,BufRead};
+use async_std::io as aio;
+use futures::io::AsyncBufReadExt;
+use async_compression::futures::bufread::{GzipDecoder,BzDecoder,ZstdDecoder};
+use crate::Error;
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Compression {
+  None(),
+  Gzip(),
+  Bzip2(),
+  Zstd(),
+}
+
+impl Compression {
+  // sniff the magic bytes at the start of a stream
+  pub fn detect(buf: &[u8]) -> Self {
+    if buf.starts_with(&[0x1f,0x8b]) {
+      Compression::Gzip()
+    } else if buf.starts_with(b"BZh") {
+      Compression::Bzip2()
+    } else if buf.starts_with(&[0x28,0xb5,0x2f,0xfd]) {
+      Compression::Zstd()
+    } else {
+      Compression::None()
+    }
+  }
+}
+
+// wrap a reader with a decoder if it starts with gzip, bzip2, or zstd magic bytes.
+// uncompressed input is passed through as-is
+pub fn decompress<'a, R: BufRead+Send+'a>(mut reader: R) -> Result<Box<dyn BufRead+Send+'a>,Error> {
+  Ok(match Compression::detect(reader.fill_buf()?) {
+    Compression::None() => Box::new(reader),
+    Compression::Gzip() => Box::new(io::BufReader::new(
+      flate2::bufread::MultiGzDecoder::new(reader)
+    )),
+    Compression::Bzip2() => Box::new(io::BufReader::new(
+      bzip2::bufread::MultiBzDecoder::new(reader)
+    )),
+    Compression::Zstd() => Box::new(io::BufReader::new(
+      zstd::stream::read::Decoder::with_buffer(reader)?
+    )),
+  })
+}
+
+// async version of decompress() for the o5m and o5c readers
+pub async fn decompress_async(reader: Box<dyn aio::Read+Unpin>) -> Result<Box<dyn aio::Read+Unpin>,Error> {
+  let mut reader = aio::BufReader::new(reader);
+  Ok(match Compression::detect(reader.fill_buf().await?) {
+    Compression::None() => Box::new(reader),
+    Compression::Gzip() => {
+      let mut decoder = GzipDecoder::new(reader);
+      decoder.multiple_members(true);
+      Box::new(decoder)
+    },
+    Compression::Bzip2() => {
+      let mut decoder = BzDecoder::new(reader);
+      decoder.multiple_members(true);
+      Box::new(decoder)
+    },
+    Compression::Zstd() => {
+      let mut decoder = ZstdDecoder::new(reader);
+      decoder.multiple_members(true);
+      Box::new(decoder)
+    },
+  })
+}
@@ -1,19 +1,88 @@
 use desert::{ToBytesBE,FromBytesBE,varint};
-use crate::{Error,error::IngestErrorKind as E};
+use crate::{Error,error::IngestErrorKind as E,xml,filter::{Filter,filter_tags},mapping::{Mapping,parse_tags},label::{LabelPolicy,select_labels},
+  TAGS_PREFIX,INFO_PREFIX,MEMBERS_PREFIX};
 
 pub const ID_PREFIX: u8 = 0;
 
+// how node locations are laid out in the level db, set by the schema version
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Coords {
+  Float(), // f32 lon, lat (schema version 1)
+  Fixed(), // i32 lon, lat in 1e-7 degrees like osm itself (schema version 2)
+}
+
+impl Coords {
+  pub fn from_schema_version(version: u64) -> Self {
+    match version {
+      0 | 1 => Coords::Float(),
+      _ => Coords::Fixed(),
+    }
+  }
+
+  // write a node location. sources pass both forms so that each layout
+  // gets the most precise value available
+  pub fn write(&self, float: (f32,f32), fixed: (i32,i32), buf: &mut [u8]) -> Result<usize,Error> {
+    let mut offset = 0;
+    match self {
+      Coords::Float() => {
+        offset += float.0.write_bytes_be(&mut buf[offset..])?;
+        offset += float.1.write_bytes_be(&mut buf[offset..])?;
+      },
+      Coords::Fixed() => {
+        offset += fixed.0.write_bytes_be(&mut buf[offset..])?;
+        offset += fixed.1.write_bytes_be(&mut buf[offset..])?;
+      },
+    }
+    Ok(offset)
+  }
+
+  // read a node location as degrees. f64 holds both layouts exactly,
+  // so nothing is lost before the conversion to f32 for georender and eyros
+  pub fn read(&self, buf: &[u8]) -> Result<(usize,(f64,f64)),Error> {
+    Ok(match self {
+      Coords::Float() => {
+        let (s0,lon) = f32::from_bytes_be(&buf[0..])?;
+        let (s1,lat) = f32::from_bytes_be(&buf[s0..])?;
+        (s0+s1,(lon as f64,lat as f64))
+      },
+      Coords::Fixed() => {
+        let (s0,lon) = i32::from_bytes_be(&buf[0..])?;
+        let (s1,lat) = i32::from_bytes_be(&buf[s0..])?;
+        (s0+s1,(from_fixed(lon),from_fixed(lat)))
+      },
+    })
+  }
+}
+
+pub fn to_fixed(x: f64) -> i32 {
+  (x*1e7).round() as i32
+}
+
+pub fn from_fixed(x: i32) -> f64 {
+  (x as f64)/1e7
+}
+
 #[derive(Debug,Clone,PartialEq)]
 pub enum Decoded {
   Node(DecodedNode),
   Way(DecodedWay),
   Relation(DecodedRelation)
 }
+impl Decoded {
+  pub fn ex_id(&self) -> u64 {
+    match self {
+      Decoded::Node(node) => node.id*3+0,
+      Decoded::Way(way) => way.id*3+1,
+      Decoded::Relation(relation) => relation.id*3+2,
+    }
+  }
+}
+
 #[derive(Debug,Clone,PartialEq)]
 pub struct DecodedNode {
   pub id: u64,
-  pub lon: f32,
-  pub lat: f32,
+  pub lon: f64,
+  pub lat: f64,
   pub feature_type: u64,
   pub labels: Vec<u8>,
 }
@@ -30,175 +99,250 @@ pub struct DecodedRelation {
   pub id: u64,
   pub feature_type: u64,
   pub is_area: bool,
-  pub members: Vec<u64>, // id*2 + (1 for inner, 0 for outer)
+  pub members: Vec<u64>, // id*2 + (1 for inner, 0 for outer or no role)
   pub labels: Vec<u8>,
 }
 
-pub fn encode_osmpbf(element: &osmpbf::Element) -> Result<(Vec<u8>,Vec<u8>),Error> {
-  let tags = match element {
-    osmpbf::Element::Node(node) => node.tags().collect::<Vec<_>>(),
-    osmpbf::Element::DenseNode(node) => node.tags().collect::<Vec<_>>(),
-    osmpbf::Element::Way(way) => way.tags().collect::<Vec<_>>(),
-    osmpbf::Element::Relation(relation) => relation.tags().collect::<Vec<_>>(),
-  };
-  let (ft,labels) = georender_pack::tags::parse(&tags)?;
-  let ex_id = match element {
-    osmpbf::Element::Node(node) => (node.id() as u64)*3+0,
-    osmpbf::Element::DenseNode(node) => (node.id() as u64)*3+0,
-    osmpbf::Element::Way(way) => (way.id() as u64)*3+1,
-    osmpbf::Element::Relation(relation) => (relation.id() as u64)*3+2,
-  };
+// how the records of an element are encoded
+#[derive(Clone,Copy)]
+pub struct EncodeOptions<'a> {
+  pub coords: Coords,
+  pub raw_tags: bool,
+  pub element_info: bool,
+  pub filter: Option<&'a Filter>,
+  pub mapping: Option<&'a Mapping>,
+  pub policy: Option<&'a LabelPolicy>,
+}
 
-  let id_bytes = id_key(ex_id)?;
-  match element {
-    osmpbf::Element::Node(node) => {
-      let mut buf = vec![0u8;4+4+varint::length(ft)+labels.len()];
-      let mut offset = 0;
-      offset += (node.lon() as f32).write_bytes_be(&mut buf[offset..])?;
-      offset += (node.lat() as f32).write_bytes_be(&mut buf[offset..])?;
-      offset += varint::encode(ft, &mut buf[offset..])?;
-      buf[offset..].copy_from_slice(&labels);
-      Ok((id_bytes,buf))
-    },
-    osmpbf::Element::DenseNode(node) => {
-      let mut buf = vec![0u8;4+4+varint::length(ft)+labels.len()];
-      let mut offset = 0;
-      offset += (node.lon() as f32).write_bytes_be(&mut buf[offset..])?;
-      offset += (node.lat() as f32).write_bytes_be(&mut buf[offset..])?;
-      offset += varint::encode(ft, &mut buf[offset..])?;
-      buf[offset..].copy_from_slice(&labels);
-      Ok((id_bytes,buf))
-    },
-    osmpbf::Element::Way(way) => {
-      let refs: Vec<i64> = way.refs().into_iter().collect();
-      let rsize = varint::length(refs.len() as u64)
-        + refs.iter().fold(0usize,|sum,r| sum + varint::length(*r as u64));
-      let tagv = way.tags().collect::<Vec<_>>();
-      let is_area = osm_is_area::way(&tagv, &way.refs().collect::<Vec<_>>()) as u64;
-      let fta = ft*2+is_area;
-      let mut buf = vec![0u8;varint::length(fta)+rsize+labels.len()];
-      let mut offset = 0;
-      offset += varint::encode(fta, &mut buf[offset..])?;
-      offset += varint::encode(refs.len() as u64, &mut buf[offset..])?;
-      for r in refs.iter() {
-        offset += varint::encode(*r as u64, &mut buf[offset..])?;
-      }
-      buf[offset..].copy_from_slice(&labels);
-      Ok((id_bytes,buf))
-    },
-    osmpbf::Element::Relation(relation) => {
-      let members: Vec<u64> = relation.members()
-        .filter(|m| {
-          let role = m.role().unwrap();
-          m.member_type == osmpbf::RelMemberType::Way
-            && (role == "inner" || role == "outer")
-        })
-        .map(|m| {
-          (m.member_id as u64)*2 + match m.role().unwrap() { "inner" => 1, _ => 0 }
-        })
-        .collect();
-      let msize = varint::length(members.len() as u64)
-        + members.iter().fold(0usize,|sum,m| sum + varint::length(*m));
-      let tagv = relation.tags().collect::<Vec<_>>();
-      let is_area = osm_is_area::relation(&tagv, &vec![1]) as u64;
-      let fta = ft*2+is_area;
-      let mut buf = vec![0u8;varint::length(fta)+msize+labels.len()];
-      let mut offset = 0;
-      offset += varint::encode(fta, &mut buf[offset..])?;
-      offset += varint::encode(members.len() as u64, &mut buf[offset..])?;
-      for m in members.iter() {
-        offset += varint::encode(*m as u64, &mut buf[offset..])?;
-      }
-      buf[offset..].copy_from_slice(&labels);
-      Ok((id_bytes,buf))
-    },
+impl<'a> EncodeOptions<'a> {
+  pub fn new(coords: Coords) -> Self {
+    Self {
+      coords,
+      raw_tags: false,
+      element_info: false,
+      filter: None,
+      mapping: None,
+      policy: None,
+    }
   }
 }
 
-pub fn encode_o5m(dataset: &o5m_stream::Dataset) -> Result<Option<Vec<u8>>,Error> {
-  let tags = match dataset {
-    o5m_stream::Dataset::Node(node) => &node.tags,
-    o5m_stream::Dataset::Way(way) => &way.tags,
-    o5m_stream::Dataset::Relation(relation) => &relation.tags,
-    _ => { return Ok(None) },
-  };
-  let tagv = tags.iter()
-    .map(|(k,v)| (k.as_str(),v.as_str()))
-    .collect::<Vec<_>>();
-  let (ft,labels) = georender_pack::tags::parse(&tagv)?;
-  match dataset {
-    o5m_stream::Dataset::Node(node) => {
-      Ok(if let Some(data) = &node.data {
-        let mut buf = vec![0u8;4+4+varint::length(ft)+labels.len()];
-        let mut offset = 0;
-        offset += data.get_longitude().write_bytes_be(&mut buf[offset..])?;
-        offset += data.get_latitude().write_bytes_be(&mut buf[offset..])?;
-        offset += varint::encode(ft, &mut buf[offset..])?;
-        buf[offset..].copy_from_slice(&labels);
-        Some(buf)
-      } else {
-        None
-      })
-    },
-    o5m_stream::Dataset::Way(way) => {
-      Ok(if let Some(data) = &way.data {
-        let rsize = varint::length(data.refs.len() as u64)
-          + data.refs.iter().fold(0usize,|sum,r| sum + varint::length(*r));
-        let is_area = osm_is_area::way(&tagv, &data.refs) as u64;
-        let fta = ft*2+is_area;
-        let mut buf = vec![0u8;varint::length(fta)+rsize+labels.len()];
-        let mut offset = 0;
-        offset += varint::encode(fta, &mut buf[offset..])?;
-        offset += varint::encode(data.refs.len() as u64, &mut buf[offset..])?;
-        for r in data.refs.iter() {
-          offset += varint::encode(*r, &mut buf[offset..])?;
+// an element of any of the input formats, borrowed for encoding its records
+#[derive(Debug,Clone,PartialEq)]
+pub struct Element<'a> {
+  pub ex_id: u64,
+  pub tags: Vec<(&'a str,&'a str)>,
+  pub body: Body<'a>,
+  pub info: Option<ElementInfo>,
+}
+#[derive(Debug,Clone,PartialEq)]
+pub enum Body<'a> {
+  Node((i32,i32)), // lon, lat in 1e-7 degrees
+  Way(Vec<u64>),
+  Relation(Vec<Member<'a>>),
+}
+#[derive(Debug,Clone,PartialEq)]
+pub struct Member<'a> {
+  pub ex_id: u64,
+  pub role: &'a str,
+}
+
+impl<'a> Element<'a> {
+  pub fn from_osmpbf(element: &'a osmpbf::Element<'a>) -> Result<Self,Error> {
+    fn from_info(info: &osmpbf::Info) -> ElementInfo {
+      ElementInfo {
+        version: info.version().unwrap_or(0).max(0) as u64,
+        timestamp: info.milli_timestamp().unwrap_or(0)/1000,
+        changeset: info.changeset().unwrap_or(0).max(0) as u64,
+        uid: info.uid().unwrap_or(0).max(0) as u64,
+      }
+    }
+    Ok(match element {
+      osmpbf::Element::Node(node) => Self {
+        ex_id: (node.id() as u64)*3+0,
+        tags: node.tags().collect(),
+        body: Body::Node((node.decimicro_lon(),node.decimicro_lat())),
+        info: Some(from_info(&node.info())),
+      },
+      osmpbf::Element::DenseNode(node) => Self {
+        ex_id: (node.id() as u64)*3+0,
+        tags: node.tags().collect(),
+        body: Body::Node((node.decimicro_lon(),node.decimicro_lat())),
+        info: Some(match node.info() {
+          Some(info) => ElementInfo {
+            version: info.version().max(0) as u64,
+            timestamp: info.milli_timestamp()/1000,
+            changeset: info.changeset().max(0) as u64,
+            uid: info.uid().max(0) as u64,
+          },
+          None => ElementInfo::default(),
+        }),
+      },
+      osmpbf::Element::Way(way) => Self {
+        ex_id: (way.id() as u64)*3+1,
+        tags: way.tags().collect(),
+        body: Body::Way(way.refs().map(|r| r as u64).collect()),
+        info: Some(from_info(&way.info())),
+      },
+      osmpbf::Element::Relation(relation) => {
+        let mut members = vec![];
+        for m in relation.members() {
+          let t = match m.member_type {
+            osmpbf::RelMemberType::Node => 0,
+            osmpbf::RelMemberType::Way => 1,
+            osmpbf::RelMemberType::Relation => 2,
+          };
+          members.push(Member { ex_id: (m.member_id as u64)*3+t, role: m.role()? });
         }
-        buf[offset..].copy_from_slice(&labels);
-        Some(buf)
-      } else {
-        None
-      })
-    },
-    o5m_stream::Dataset::Relation(relation) => {
-      Ok(if let Some(data) = &relation.data {
-        let members: Vec<u64> = data.members.iter()
-          .filter(|m| {
-            m.element_type == o5m_stream::ElementType::Way()
-              && (m.role == "inner" || m.role == "outer")
-          })
-          .map(|m| m.id*2 + match m.role.as_str() { "inner" => 1, _ => 0 })
-          .collect();
-        let msize = varint::length(members.len() as u64)
-          + members.iter().fold(0usize,|sum,m| sum + varint::length(*m));
-        let is_area = osm_is_area::relation(&tagv, &vec![1]) as u64;
-        let fta = ft*2+is_area;
-        let mut buf = vec![0u8;varint::length(fta)+msize+labels.len()];
-        let mut offset = 0;
-        offset += varint::encode(fta, &mut buf[offset..])?;
-        offset += varint::encode(members.len() as u64, &mut buf[offset..])?;
-        for m in members.iter() {
-          offset += varint::encode(*m as u64, &mut buf[offset..])?;
+        Self {
+          ex_id: (relation.id() as u64)*3+2,
+          tags: relation.tags().collect(),
+          body: Body::Relation(members),
+          info: Some(from_info(&relation.info())),
         }
-        buf[offset..].copy_from_slice(&labels);
-        Some(buf)
-      } else {
-        None
-      })
-    },
-    _ => Ok(None),
+      },
+    })
+  }
+
+  pub fn from_xml(element: &'a xml::Element) -> Self {
+    let (ex_id,body,info) = match element {
+      xml::Element::Node(node) => {
+        (node.id*3+0, Body::Node((to_fixed(node.lon),to_fixed(node.lat))), &node.info)
+      },
+      xml::Element::Way(way) => (way.id*3+1, Body::Way(way.refs.clone()), &way.info),
+      xml::Element::Relation(relation) => {
+        let members = relation.members.iter().map(|m| Member {
+          ex_id: m.id*3 + match m.member_type {
+            xml::MemberType::Node() => 0,
+            xml::MemberType::Way() => 1,
+            xml::MemberType::Relation() => 2,
+          },
+          role: m.role.as_str(),
+        }).collect();
+        (relation.id*3+2, Body::Relation(members), &relation.info)
+      },
+    };
+    Self {
+      ex_id,
+      tags: element.get_tags(),
+      body,
+      info: info.as_ref().map(ElementInfo::from_o5m),
+    }
+  }
+
+  // datasets without data (deletes) have nothing to encode
+  pub fn from_o5m(dataset: &'a o5m_stream::Dataset) -> Option<Self> {
+    let (ex_id,tags,body) = match dataset {
+      o5m_stream::Dataset::Node(node) => {
+        let data = node.data.as_ref()?;
+        (node.id*3+0, &node.tags, Body::Node((data.longitude,data.latitude)))
+      },
+      o5m_stream::Dataset::Way(way) => {
+        (way.id*3+1, &way.tags, Body::Way(way.data.as_ref()?.refs.clone()))
+      },
+      o5m_stream::Dataset::Relation(relation) => {
+        let members = relation.data.as_ref()?.members.iter().map(|m| Member {
+          ex_id: m.id*3 + match m.element_type {
+            o5m_stream::ElementType::Node() => 0,
+            o5m_stream::ElementType::Way() => 1,
+            o5m_stream::ElementType::Relation() => 2,
+          },
+          role: m.role.as_str(),
+        }).collect();
+        (relation.id*3+2, &relation.tags, Body::Relation(members))
+      },
+      _ => return None,
+    };
+    Some(Self {
+      ex_id,
+      tags: tags.iter().map(|(k,v)| (k.as_str(),v.as_str())).collect(),
+      body,
+      info: dataset.get_info().map(|info| ElementInfo::from_o5m(&info)),
+    })
   }
 }
 
-pub fn decode(key: &[u8], value: &[u8]) -> Result<Decoded,Error> {
+// the id record of an element
+pub fn encode_element(element: &Element, opts: &EncodeOptions) -> Result<(Vec<u8>,Vec<u8>),Error> {
+  let (ex_id,tags) = (element.ex_id, &element.tags);
+  match &element.body {
+    Body::Node(lonlat) => encode_node(ex_id, tags, *lonlat, opts),
+    Body::Way(refs) => encode_way(ex_id, tags, refs, opts),
+    Body::Relation(members) => encode_relation(ex_id, tags, members, opts),
+  }
+}
+
+pub fn encode_node(ex_id: u64, tags: &[(&str,&str)], lonlat: (i32,i32), opts: &EncodeOptions)
+-> Result<(Vec<u8>,Vec<u8>),Error> {
+  let tags = filter_tags(opts.filter, 0, tags.to_vec());
+  let (ft,labels,_) = parse_tags(opts.mapping, 0, &tags)?;
+  let labels = select_labels(opts.policy, labels)?;
+  let float = (from_fixed(lonlat.0) as f32, from_fixed(lonlat.1) as f32);
+  Ok((id_key(ex_id)?,node_value(opts.coords, float, lonlat, ft, &labels)?))
+}
+
+pub fn encode_way(ex_id: u64, tags: &[(&str,&str)], refs: &[u64], opts: &EncodeOptions)
+-> Result<(Vec<u8>,Vec<u8>),Error> {
+  let tags = filter_tags(opts.filter, 1, tags.to_vec());
+  let (ft,labels,area) = parse_tags(opts.mapping, 1, &tags)?;
+  let labels = select_labels(opts.policy, labels)?;
+  let is_area = area.unwrap_or_else(|| osm_is_area::way(&tags, refs));
+  Ok((id_key(ex_id)?,list_value(ft, is_area, refs, &labels)?))
+}
+
+// only inner and outer way members (or ways without a role, for areas) are
+// part of the record. the others are kept under MEMBERS_PREFIX
+pub fn encode_relation(ex_id: u64, tags: &[(&str,&str)], members: &[Member], opts: &EncodeOptions)
+-> Result<(Vec<u8>,Vec<u8>),Error> {
+  let tags = filter_tags(opts.filter, 2, tags.to_vec());
+  let (ft,labels,area) = parse_tags(opts.mapping, 2, &tags)?;
+  let labels = select_labels(opts.policy, labels)?;
+  let is_area = area.unwrap_or_else(|| osm_is_area::relation(&tags, &vec![1]));
+  let ways = members.iter()
+    .filter(|m| {
+      m.ex_id%3 == 1 && (m.role == "inner" || m.role == "outer" || (is_area && m.role.is_empty()))
+    })
+    .map(|m| (m.ex_id/3)*2 + match m.role { "inner" => 1, _ => 0 })
+    .collect::<Vec<u64>>();
+  Ok((id_key(ex_id)?,list_value(ft, is_area, &ways, &labels)?))
+}
+
+// a node record is its location, feature type, and labels
+fn node_value(coords: Coords, float: (f32,f32), fixed: (i32,i32), ft: u64, labels: &[u8])
+-> Result<Vec<u8>,Error> {
+  let mut buf = vec![0u8;4+4+varint::length(ft)+labels.len()];
+  let mut offset = 0;
+  offset += coords.write(float, fixed, &mut buf[offset..])?;
+  offset += varint::encode(ft, &mut buf[offset..])?;
+  buf[offset..].copy_from_slice(labels);
+  Ok(buf)
+}
+
+// way and relation records are feature type*2 + area, their refs or members,
+// and labels
+fn list_value(ft: u64, is_area: bool, list: &[u64], labels: &[u8]) -> Result<Vec<u8>,Error> {
+  let fta = ft*2+(is_area as u64);
+  let size = varint::length(fta) + varint::length(list.len() as u64)
+    + list.iter().fold(0usize,|sum,x| sum + varint::length(*x));
+  let mut buf = vec![0u8;size+labels.len()];
+  let mut offset = 0;
+  offset += varint::encode(fta, &mut buf[offset..])?;
+  offset += varint::encode(list.len() as u64, &mut buf[offset..])?;
+  for x in list.iter() {
+    offset += varint::encode(*x, &mut buf[offset..])?;
+  }
+  buf[offset..].copy_from_slice(labels);
+  Ok(buf)
+}
+
+pub fn decode(key: &[u8], value: &[u8], coords: Coords) -> Result<Decoded,Error> {
   if key[0] != ID_PREFIX { E::NonIdKey { prefix: key[0] }.raise()? }
   let (_,ex_id) = varint::decode(&key[1..])?;
   let id = ex_id/3;
   Ok(match ex_id%3 {
     0 => {
       let mut offset = 0;
-      let (s,lon) = f32::from_bytes_be(&value[offset..])?;
-      offset += s;
-      let (s,lat) = f32::from_bytes_be(&value[offset..])?;
+      let (s,(lon,lat)) = coords.read(&value[offset..])?;
       offset += s;
       let (s,feature_type) = varint::decode(&value[offset..])?;
       offset += s;
@@ -242,6 +386,250 @@ pub fn decode(key: &[u8], value: &[u8]) -> Result<Decoded,Error> {
   })
 }
 
+// encode a decoded record back into its value
+pub fn encode_record(decoded: &Decoded, coords: Coords) -> Result<Vec<u8>,Error> {
+  match decoded {
+    Decoded::Node(node) => node_value(coords,
+      (node.lon as f32, node.lat as f32),
+      (to_fixed(node.lon), to_fixed(node.lat)),
+      node.feature_type, &node.labels
+    ),
+    Decoded::Way(way) => list_value(way.feature_type, way.is_area, &way.refs, &way.labels),
+    Decoded::Relation(relation) => {
+      list_value(relation.feature_type, relation.is_area, &relation.members, &relation.labels)
+    },
+  }
+}
+
+// raw tags are stored under TAGS_PREFIX with the same varint ex_id as the record
+// so that the feature type and labels can be derived again with a new tag mapping
+
+pub fn encode_element_tags(element: &Element) -> Result<(Vec<u8>,Vec<u8>),Error> {
+  Ok((tags_key(element.ex_id)?,encode_tags(&element.tags)?))
+}
+
+// count followed by length-prefixed key and value strings
+pub fn encode_tags(tags: &[(&str,&str)]) -> Result<Vec<u8>,Error> {
+  let size = varint::length(tags.len() as u64) + tags.iter().fold(0usize,|sum,(k,v)| {
+    sum + varint::length(k.len() as u64) + k.len() + varint::length(v.len() as u64) + v.len()
+  });
+  let mut buf = vec![0u8;size];
+  let mut offset = 0;
+  offset += varint::encode(tags.len() as u64, &mut buf[offset..])?;
+  for (k,v) in tags.iter() {
+    for x in [k,v].iter() {
+      offset += varint::encode(x.len() as u64, &mut buf[offset..])?;
+      buf[offset..offset+x.len()].copy_from_slice(x.as_bytes());
+      offset += x.len();
+    }
+  }
+  Ok(buf)
+}
+
+pub fn decode_tags(buf: &[u8]) -> Result<Vec<(String,String)>,Error> {
+  let mut offset = 0;
+  let (s,len) = varint::decode(&buf[offset..])?;
+  offset += s;
+  let mut tags = Vec::with_capacity(len as usize);
+  for _ in 0..len {
+    let mut kv = [String::new(),String::new()];
+    for x in kv.iter_mut() {
+      let (s,n) = varint::decode(&buf[offset..])?;
+      offset += s;
+      *x = String::from_utf8(buf[offset..offset+(n as usize)].to_vec())?;
+      offset += n as usize;
+    }
+    let [k,v] = kv;
+    tags.push((k,v));
+  }
+  Ok(tags)
+}
+
+// derive the feature type, labels, and area flag of a record from its raw tags
+pub fn retag(decoded: &Decoded, tags: &[(String,String)], mapping: Option<&Mapping>,
+  policy: Option<&LabelPolicy>
+) -> Result<Decoded,Error> {
+  let tagv = tags.iter()
+    .map(|(k,v)| (k.as_str(),v.as_str()))
+    .collect::<Vec<_>>();
+  let (feature_type,labels,area) = parse_tags(mapping, decoded.ex_id()%3, &tagv)?;
+  let labels = select_labels(policy, labels)?;
+  Ok(match decoded {
+    Decoded::Node(node) => Decoded::Node(DecodedNode {
+      feature_type,
+      labels,
+      ..node.clone()
+    }),
+    Decoded::Way(way) => Decoded::Way(DecodedWay {
+      feature_type,
+      labels,
+      is_area: area.unwrap_or_else(|| osm_is_area::way(&tagv, &way.refs)),
+      ..way.clone()
+    }),
+    Decoded::Relation(relation) => Decoded::Relation(DecodedRelation {
+      feature_type,
+      labels,
+      is_area: area.unwrap_or_else(|| osm_is_area::relation(&tagv, &[1])),
+      ..relation.clone()
+    }),
+  })
+}
+
+pub fn tags_key(ex_id: u64) -> Result<Vec<u8>,Error> {
+  let mut key = vec![0u8;1+varint::length(ex_id)];
+  key[0] = TAGS_PREFIX;
+  varint::encode(ex_id, &mut key[1..])?;
+  Ok(key)
+}
+
+// version metadata of an element, stored under INFO_PREFIX with the same
+// varint ex_id as the record. zero means the source didn't say
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct ElementInfo {
+  pub version: u64,
+  pub timestamp: i64, // seconds since the epoch
+  pub changeset: u64,
+  pub uid: u64,
+}
+
+impl ElementInfo {
+  pub fn from_o5m(info: &o5m_stream::Info) -> Self {
+    Self {
+      version: info.version.unwrap_or(0),
+      timestamp: info.timestamp.unwrap_or(0),
+      changeset: info.changeset.unwrap_or(0),
+      uid: info.uid.unwrap_or(0),
+    }
+  }
+
+  // whether this is a later edit than the stored one. versions are compared
+  // when both are known, otherwise timestamps. missing metadata is never stale
+  pub fn supersedes(&self, stored: &ElementInfo) -> bool {
+    if self.version > 0 && stored.version > 0 {
+      self.version > stored.version
+    } else if self.timestamp > 0 && stored.timestamp > 0 {
+      self.timestamp > stored.timestamp
+    } else {
+      true
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self == &Self::default()
+  }
+}
+
+pub fn encode_info(info: &ElementInfo) -> Result<Vec<u8>,Error> {
+  let fields = [info.version, info.timestamp.max(0) as u64, info.changeset, info.uid];
+  let mut buf = vec![0u8;fields.iter().fold(0usize,|sum,x| sum + varint::length(*x))];
+  let mut offset = 0;
+  for x in fields.iter() {
+    offset += varint::encode(*x, &mut buf[offset..])?;
+  }
+  Ok(buf)
+}
+
+pub fn decode_info(buf: &[u8]) -> Result<ElementInfo,Error> {
+  let mut fields = [0u64;4];
+  let mut offset = 0;
+  for x in fields.iter_mut() {
+    let (s,v) = varint::decode(&buf[offset..])?;
+    offset += s;
+    *x = v;
+  }
+  Ok(ElementInfo {
+    version: fields[0],
+    timestamp: fields[1] as i64,
+    changeset: fields[2],
+    uid: fields[3],
+  })
+}
+
+pub fn encode_element_info(element: &Element) -> Result<Option<(Vec<u8>,Vec<u8>)>,Error> {
+  Ok(match &element.info {
+    Some(info) => Some((info_key(element.ex_id)?,encode_info(info)?)),
+    None => None,
+  })
+}
+
+pub fn info_key(ex_id: u64) -> Result<Vec<u8>,Error> {
+  let mut key = vec![0u8;1+varint::length(ex_id)];
+  key[0] = INFO_PREFIX;
+  varint::encode(ex_id, &mut key[1..])?;
+  Ok(key)
+}
+
+// node and relation members of a relation, stored under MEMBERS_PREFIX with
+// the same varint ex_id as the record since way members are part of the record
+// itself. route relations (type=route) also keep all of their way members here
+// in order, whatever their role, so they can be drawn as lines. each member is
+// its ex_id*4 + role, where the role is one of these
+pub const ROLE_OTHER: u64 = 0;
+pub const ROLE_OUTER: u64 = 1;
+pub const ROLE_INNER: u64 = 2;
+
+pub fn member_role(role: &str) -> u64 {
+  match role {
+    "outer" => ROLE_OUTER,
+    "inner" => ROLE_INNER,
+    _ => ROLE_OTHER,
+  }
+}
+
+pub fn encode_element_members(element: &Element) -> Result<Option<(Vec<u8>,Vec<u8>)>,Error> {
+  let members = match &element.body {
+    Body::Relation(members) => members,
+    _ => return Ok(None),
+  };
+  let route = is_route(&element.tags);
+  let members = members.iter()
+    .filter(|m| route || m.ex_id%3 != 1)
+    .map(|m| m.ex_id*4 + member_role(m.role))
+    .collect::<Vec<u64>>();
+  relation_members(element.ex_id, &members)
+}
+
+pub fn is_route(tags: &[(&str,&str)]) -> bool {
+  tags.iter().any(|(k,v)| *k == "type" && *v == "route")
+}
+
+// no record for relations with only way members, unless they are routes
+fn relation_members(ex_id: u64, members: &[u64]) -> Result<Option<(Vec<u8>,Vec<u8>)>,Error> {
+  if members.is_empty() { return Ok(None) }
+  Ok(Some((members_key(ex_id)?,encode_members(members)?)))
+}
+
+pub fn encode_members(members: &[u64]) -> Result<Vec<u8>,Error> {
+  let mut buf = vec![0u8;varint::length(members.len() as u64)
+    + members.iter().fold(0usize,|sum,m| sum + varint::length(*m))];
+  let mut offset = 0;
+  offset += varint::encode(members.len() as u64, &mut buf[offset..])?;
+  for m in members.iter() {
+    offset += varint::encode(*m, &mut buf[offset..])?;
+  }
+  Ok(buf)
+}
+
+pub fn decode_members(buf: &[u8]) -> Result<Vec<u64>,Error> {
+  let mut offset = 0;
+  let (s,len) = varint::decode(&buf[offset..])?;
+  offset += s;
+  let mut members = Vec::with_capacity(len as usize);
+  for _ in 0..len {
+    let (s,m) = varint::decode(&buf[offset..])?;
+    offset += s;
+    members.push(m);
+  }
+  Ok(members)
+}
+
+pub fn members_key(ex_id: u64) -> Result<Vec<u8>,Error> {
+  let mut key = vec![0u8;1+varint::length(ex_id)];
+  key[0] = MEMBERS_PREFIX;
+  varint::encode(ex_id, &mut key[1..])?;
+  Ok(key)
+}
+
 pub fn id_key(ex_id: u64) -> Result<Vec<u8>,Error> {
   let mut id_bytes = vec![0u8;1+varint::length(ex_id)];
   id_bytes[0] = ID_PREFIX;
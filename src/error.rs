@@ -11,6 +11,23 @@ pub struct IngestError {
 #[derive(Debug)]
 pub enum IngestErrorKind {
   NonIdKey { prefix: u8 },
+  XmlMissingAttribute { element: String, attribute: String },
+  XmlInvalidAttribute { element: String, attribute: String, value: String },
+  ReplicationStateMissingKey { key: String },
+  MissingReplicationSequence,
+  InvalidPbfHeader { message: String },
+  SchemaVersionMismatch { found: u64, expected: u64 },
+  UnsupportedMigration { from: u64, to: u64 },
+  UnsupportedSchemaVersion { version: u64 },
+  IncompleteMigration { from: u64, to: u64 },
+  InvalidBbox { value: String },
+  InvalidPolygon { message: String },
+  InvalidFilter { expr: String },
+  InvalidMapping { line: String },
+  InvalidLabelPolicy { message: String },
+  InvalidLabels { message: String },
+  InvalidMultipolygon { id: u64, message: String },
+  InvalidCoastline { message: String },
 }
 
 impl IngestErrorKind {
@@ -34,6 +51,64 @@ impl std::fmt::Display for IngestError {
       IngestErrorKind::NonIdKey { prefix } => {
         write![f, "expected {} (ID_PREFIX), found {}", ID_PREFIX, prefix]
       },
+      IngestErrorKind::XmlMissingAttribute { element, attribute } => {
+        write![f, "missing required attribute {} on <{}>", attribute, element]
+      },
+      IngestErrorKind::XmlInvalidAttribute { element, attribute, value } => {
+        write![f, "invalid value {:?} for attribute {} on <{}>", value, attribute, element]
+      },
+      IngestErrorKind::ReplicationStateMissingKey { key } => {
+        write![f, "replication state file is missing {}", key]
+      },
+      IngestErrorKind::MissingReplicationSequence => {
+        write![f, "no replication sequence stored in level db, provide a starting sequence"]
+      },
+      IngestErrorKind::InvalidPbfHeader { message } => {
+        write![f, "invalid pbf header: {}", message]
+      },
+      IngestErrorKind::SchemaVersionMismatch { found, expected } if found < expected => {
+        write![f, "level db has schema version {} but {} is required, run the migrate command",
+          found, expected]
+      },
+      IngestErrorKind::SchemaVersionMismatch { found, expected } => {
+        write![f, "level db has schema version {} which is newer than the expected version {}",
+          found, expected]
+      },
+      IngestErrorKind::UnsupportedMigration { from, to } => {
+        write![f, "no migration from schema version {} to {}", from, to]
+      },
+      IngestErrorKind::UnsupportedSchemaVersion { version } => {
+        write![f, "unsupported schema version {}, expected 1 to {}",
+          version, crate::meta::LATEST_SCHEMA_VERSION]
+      },
+      IngestErrorKind::IncompleteMigration { from, to } => {
+        write![f, "level db was left partly migrated from schema version {} to {}, restore it from a backup",
+          from, to]
+      },
+      IngestErrorKind::InvalidBbox { value } => {
+        write![f, "invalid bbox {:?}, expected minlon,minlat,maxlon,maxlat", value]
+      },
+      IngestErrorKind::InvalidPolygon { message } => {
+        write![f, "invalid polygon: {}", message]
+      },
+      IngestErrorKind::InvalidFilter { expr } => {
+        write![f, "invalid tag filter {:?}, expected [!][nwr/]key[=value,...]", expr]
+      },
+      IngestErrorKind::InvalidMapping { line } => {
+        write![f, "invalid type mapping {:?}, expected [nwr/]key[=value,...] type [priority] [area|line]", line]
+      },
+      IngestErrorKind::InvalidLabelPolicy { message } => {
+        write![f, "invalid label policy: {}", message]
+      },
+      IngestErrorKind::InvalidLabels { message } => {
+        write![f, "invalid labels: {}", message]
+      },
+      IngestErrorKind::InvalidMultipolygon { id, message } => {
+        write![f, "relation {} is not a valid multipolygon: {}", id, message]
+      },
+      IngestErrorKind::InvalidCoastline { message } => {
+        write![f, "invalid coastline: {}", message]
+      },
     }
   }
 }
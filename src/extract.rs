@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use async_std::sync::Arc;
+use desert::varint;
+use leveldb::database::Database;
+use leveldb::iterator::{Iterable,LevelDBIterator};
+use leveldb::options::ReadOptions;
+use crate::{Ingest,Phase,Error,Key,Coords,Decoded,decode,from_fixed,id_key,tags_key,info_key,members_key,
+  meta,error::IngestErrorKind as E,ID_PREFIX,EXTRACT_PREFIX};
+
+// west, south, east, north like the pbf header bbox
+pub type BBox = (f64,f64,f64,f64);
+
+// parse a bbox from a minlon,minlat,maxlon,maxlat string
+pub fn parse_bbox(value: &str) -> Result<BBox,Error> {
+  let xs = value.split(',').map(|x| x.trim().parse::<f64>()).collect::<Result<Vec<f64>,_>>();
+  Ok(match xs.as_deref() {
+    Ok([w,s,e,n]) if w <= e && s <= n => (*w,*s,*e,*n),
+    _ => E::InvalidBbox { value: value.to_string() }.raise()?,
+  })
+}
+
+pub fn contains(bbox: &BBox, lon: f64, lat: f64) -> bool {
+  bbox.0 <= lon && lon <= bbox.2 && bbox.1 <= lat && lat <= bbox.3
+}
+
+// a closed or open list of (lon,lat) vertices
+pub type Ring = Vec<(f64,f64)>;
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Polygon {
+  pub outer: Ring,
+  pub holes: Vec<Ring>,
+  bbox: BBox,
+}
+
+impl Polygon {
+  pub fn new(outer: Ring, holes: Vec<Ring>) -> Self {
+    let bbox = outer.iter().fold(
+      (f64::INFINITY,f64::INFINITY,f64::NEG_INFINITY,f64::NEG_INFINITY),
+      |b,(lon,lat)| (b.0.min(*lon), b.1.min(*lat), b.2.max(*lon), b.3.max(*lat))
+    );
+    Self { outer, holes, bbox }
+  }
+
+  pub fn contains(&self, lon: f64, lat: f64) -> bool {
+    contains(&self.bbox, lon, lat) && ring_contains(&self.outer, lon, lat)
+      && !self.holes.iter().any(|h| ring_contains(h, lon, lat))
+  }
+}
+
+// the area that an extract keeps
+#[derive(Debug,Clone,PartialEq)]
+pub enum Region {
+  Bbox(BBox),
+  Polygons(Vec<Polygon>),
+}
+
+impl Region {
+  // read an osmosis .poly file or a geojson file with polygons or multipolygons
+  pub fn from_path(path: &std::path::Path) -> Result<Self,Error> {
+    let src = std::fs::read_to_string(path)?;
+    match src.trim_start().starts_with('{') {
+      true => parse_geojson(&src),
+      false => parse_poly(&src),
+    }
+  }
+
+  pub fn contains(&self, lon: f64, lat: f64) -> bool {
+    match self {
+      Region::Bbox(bbox) => contains(bbox, lon, lat),
+      Region::Polygons(polygons) => polygons.iter().any(|p| p.contains(lon, lat)),
+    }
+  }
+
+  pub fn bbox(&self) -> BBox {
+    match self {
+      Region::Bbox(bbox) => *bbox,
+      Region::Polygons(polygons) => polygons.iter().fold(
+        (f64::INFINITY,f64::INFINITY,f64::NEG_INFINITY,f64::NEG_INFINITY),
+        |b,p| (b.0.min(p.bbox.0), b.1.min(p.bbox.1), b.2.max(p.bbox.2), b.3.max(p.bbox.3))
+      ),
+    }
+  }
+}
+
+// even-odd rule, so the closing vertex may or may not be repeated
+fn ring_contains(ring: &[(f64,f64)], lon: f64, lat: f64) -> bool {
+  let mut inside = false;
+  let mut j = ring.len().max(1) - 1;
+  for i in 0..ring.len() {
+    let ((xi,yi),(xj,yj)) = (ring[i],ring[j]);
+    if (yi > lat) != (yj > lat) && lon < (xj-xi)*(lat-yi)/(yj-yi) + xi {
+      inside = !inside;
+    }
+    j = i;
+  }
+  inside
+}
+
+// osmosis polygon filter format: a name line, then sections of "lon lat" lines
+// that each end with END, then a final END. sections with names that start
+// with ! are holes in the outer section before them
+pub fn parse_poly(src: &str) -> Result<Region,Error> {
+  let mut lines = src.lines().map(|line| line.trim()).filter(|line| !line.is_empty());
+  if lines.next().is_none() { return invalid("empty .poly file") }
+  let mut polygons: Vec<(Ring,Vec<Ring>)> = vec![];
+  loop {
+    let name = match lines.next() {
+      Some("END") => break,
+      Some(name) => name,
+      None => return invalid("missing END at the end of the .poly file"),
+    };
+    let mut ring = vec![];
+    loop {
+      match lines.next() {
+        Some("END") => break,
+        Some(line) => {
+          let xs = line.split_whitespace().map(|x| x.parse::<f64>()).collect::<Result<Vec<f64>,_>>();
+          match xs.as_deref() {
+            Ok([lon,lat]) => ring.push((*lon,*lat)),
+            _ => return invalid(&format!["expected \"lon lat\", found {:?}", line]),
+          }
+        },
+        None => return invalid(&format!["missing END for section {}", name]),
+      }
+    }
+    if ring.len() < 3 { return invalid(&format!["section {} has fewer than 3 points", name]) }
+    match (name.starts_with('!'), polygons.last_mut()) {
+      (true,Some((_,holes))) => holes.push(ring),
+      (true,None) => return invalid(&format!["hole {} comes before any outer section", name]),
+      (false,_) => polygons.push((ring,vec![])),
+    }
+  }
+  if polygons.is_empty() { return invalid("no outer rings in the .poly file") }
+  Ok(Region::Polygons(polygons.into_iter().map(|(outer,holes)| Polygon::new(outer, holes)).collect()))
+}
+
+// Polygon and MultiPolygon geometries from a FeatureCollection, Feature,
+// GeometryCollection, or bare geometry. other geometry types are ignored
+pub fn parse_geojson(src: &str) -> Result<Region,Error> {
+  let value: serde_json::Value = serde_json::from_str(src)?;
+  let mut polygons = vec![];
+  geojson_polygons(&value, &mut polygons)?;
+  if polygons.is_empty() { return invalid("no polygons in the geojson file") }
+  Ok(Region::Polygons(polygons))
+}
+
+fn geojson_polygons(value: &serde_json::Value, polygons: &mut Vec<Polygon>) -> Result<(),Error> {
+  let empty = vec![];
+  match value["type"].as_str() {
+    Some("FeatureCollection") => {
+      for feature in value["features"].as_array().unwrap_or(&empty) {
+        geojson_polygons(feature, polygons)?;
+      }
+    },
+    Some("Feature") => geojson_polygons(&value["geometry"], polygons)?,
+    Some("GeometryCollection") => {
+      for geometry in value["geometries"].as_array().unwrap_or(&empty) {
+        geojson_polygons(geometry, polygons)?;
+      }
+    },
+    Some("Polygon") => polygons.push(geojson_polygon(&value["coordinates"])?),
+    Some("MultiPolygon") => {
+      for coordinates in value["coordinates"].as_array().unwrap_or(&empty) {
+        polygons.push(geojson_polygon(coordinates)?);
+      }
+    },
+    _ => {},
+  }
+  Ok(())
+}
+
+// the first ring is the outer ring and the rest are holes
+fn geojson_polygon(coordinates: &serde_json::Value) -> Result<Polygon,Error> {
+  let mut rings = vec![];
+  for ring in coordinates.as_array().into_iter().flatten() {
+    let mut points = vec![];
+    for p in ring.as_array().into_iter().flatten() {
+      match (p[0].as_f64(), p[1].as_f64()) {
+        (Some(lon),Some(lat)) => points.push((lon,lat)),
+        _ => return invalid(&format!["invalid position {}", p]),
+      }
+    }
+    rings.push(points);
+  }
+  if rings.is_empty() || rings[0].len() < 3 { return invalid("polygon without an outer ring") }
+  let outer = rings.remove(0);
+  Ok(Polygon::new(outer, rings))
+}
+
+fn invalid<T>(message: &str) -> Result<T,Error> {
+  Ok(E::InvalidPolygon { message: message.to_string() }.raise()?)
+}
+
+// while extract runs, it keeps its state in the level db under EXTRACT_PREFIX
+// instead of in memory: kept ways, their nodes, and kept relations are
+// marked KEEP, and relations that are only kept if a member relation is are
+// marked PENDING. the marks are deleted when extract is done
+const KEEP: u8 = 1;
+const PENDING: u8 = 0;
+
+fn extract_key(ex_id: u64) -> Result<Vec<u8>,Error> {
+  let mut key = vec![0u8;1+varint::length(ex_id)];
+  key[0] = EXTRACT_PREFIX;
+  varint::encode(ex_id, &mut key[1..])?;
+  Ok(key)
+}
+
+impl Ingest {
+  // drop the records that a load wrote outside of the region: ways without a
+  // node inside it, relations without a kept member way, member relation, or
+  // member node inside it, and nodes outside of it that no kept way references.
+  // kept ways keep all of their nodes and kept multipolygons keep all of their
+  // member ways like the smart strategy of osmium extract. this runs before
+  // process, so there are no eyros rows or backrefs to clean up. each pass
+  // streams the records out of leveldb
+  pub async fn extract(&mut self) -> Result<(),Error> {
+    let region = match &self.region {
+      Some(r) => r.clone(),
+      None => return Ok(()),
+    };
+    let db = self.flushed_db().await?;
+    let coords = self.coords();
+    for (key,value) in records(&db, 1) {
+      if let Decoded::Way(way) = decode(&key.data, &value, coords)? {
+        let mut deps = HashMap::with_capacity(way.refs.len());
+        self.get_way_deps(way.refs.iter(), &mut deps).await?;
+        if deps.values().any(|(lon,lat)| region.contains(*lon as f64, *lat as f64)) {
+          self.keep_way(way.id*3+1, &way.refs).await?;
+        }
+      }
+    }
+    // relations with a kept way (including the ways of routes) or a node member
+    // inside, and then the ones with a kept relation as a member until nothing changes
+    for (key,value) in records(&db, 2) {
+      if let Decoded::Relation(relation) = decode(&key.data, &value, coords)? {
+        let ex_id = relation.id*3+2;
+        let members = self.get_member_refs(ex_id).await?;
+        let ways = relation.members.iter().map(|m| (m/2)*3+1)
+          .chain(members.iter().copied().filter(|r| r%3 == 1));
+        let nodes = members.iter().filter(|r| *r%3 == 0).map(|r| r/3).collect::<Vec<u64>>();
+        let mut node_deps = HashMap::with_capacity(nodes.len());
+        self.get_way_deps(nodes.iter(), &mut node_deps).await?;
+        if self.any_kept(ways).await?
+        || node_deps.values().any(|(lon,lat)| region.contains(*lon as f64, *lat as f64)) {
+          self.mark(ex_id, KEEP).await?;
+        } else if members.iter().any(|r| r%3 == 2) {
+          self.mark(ex_id, PENDING).await?;
+        } else {
+          self.remove_record(ex_id).await?;
+        }
+      }
+    }
+    loop {
+      let mut changed = false;
+      for (ex_id,mark) in marks(&self.flushed_db().await?) {
+        if ex_id%3 != 2 || mark != PENDING { continue }
+        for r in self.get_member_refs(ex_id).await?.iter().filter(|r| *r%3 == 2) {
+          if self.get_mark(*r).await? == Some(KEEP) {
+            self.mark(ex_id, KEEP).await?;
+            changed = true;
+            break;
+          }
+        }
+      }
+      if !changed { break }
+    }
+    // multipolygons keep their ways outside of the region so that they stay
+    // complete, such as an inner ring that is cut off by the edge
+    for (ex_id,mark) in marks(&self.flushed_db().await?) {
+      if ex_id%3 != 2 || mark != KEEP { continue }
+      let relation = match self.get_record(ex_id, coords).await? {
+        Some(Decoded::Relation(relation)) if relation.is_area => relation,
+        _ => continue,
+      };
+      for m in relation.members.iter() {
+        if let Some(Decoded::Way(way)) = self.get_record((m/2)*3+1, coords).await? {
+          self.keep_way(way.id*3+1, &way.refs).await?;
+        }
+      }
+    }
+    for (ex_id,mark) in marks(&self.flushed_db().await?) {
+      if ex_id%3 == 2 && mark == PENDING {
+        self.remove_record(ex_id).await?;
+      }
+    }
+    for (key,_) in records(&db, 1) {
+      let (_,ex_id) = varint::decode(&key.data[1..])?;
+      if self.get_mark(ex_id).await?.is_none() {
+        self.remove_record(ex_id).await?;
+      }
+    }
+    for (key,value) in records(&db, 0) {
+      if let Decoded::Node(node) = decode(&key.data, &value, coords)? {
+        if !region.contains(node.lon, node.lat) && self.get_mark(node.id*3+0).await?.is_none() {
+          self.remove_record(node.id*3+0).await?;
+        }
+      }
+    }
+    for (ex_id,_) in marks(&self.flushed_db().await?) {
+      self.lstore.lock().await.del(Key::from(&extract_key(ex_id)?))?;
+    }
+    self.put_meta("extract_bbox", &meta::encode_bbox(&region.bbox())?).await?;
+    self.lstore.lock().await.flush()?;
+    if let Some(n) = self.read_nodes().await { n.flush()? }
+    Ok(())
+  }
+
+  async fn mark(&self, ex_id: u64, mark: u8) -> Result<(),Error> {
+    self.lstore.lock().await.put(Key::from(&extract_key(ex_id)?), &[mark])
+  }
+
+  async fn get_mark(&self, ex_id: u64) -> Result<Option<u8>,Error> {
+    let buf = self.lstore.lock().await.get(&Key::from(&extract_key(ex_id)?))?;
+    Ok(buf.and_then(|b| b.first().copied()))
+  }
+
+  // write out pending changes so that iterating leveldb sees them
+  async fn flushed_db(&self) -> Result<Arc<Database<Key>>,Error> {
+    let mut lstore = self.lstore.lock().await;
+    lstore.flush()?;
+    Ok(lstore.db.clone())
+  }
+
+  async fn keep_way(&self, ex_id: u64, refs: &[u64]) -> Result<(),Error> {
+    self.mark(ex_id, KEEP).await?;
+    for r in refs.iter() {
+      self.mark(r*3+0, KEEP).await?;
+    }
+    Ok(())
+  }
+
+  async fn any_kept(&self, ex_ids: impl Iterator<Item=u64>) -> Result<bool,Error> {
+    for ex_id in ex_ids {
+      if self.get_mark(ex_id).await? == Some(KEEP) { return Ok(true) }
+    }
+    Ok(false)
+  }
+
+  async fn get_record(&self, ex_id: u64, coords: Coords) -> Result<Option<Decoded>,Error> {
+    let key = Key::from(&id_key(ex_id)?);
+    let buf = self.lstore.lock().await.get(&key)?;
+    buf.map(|buf| decode(&key.data, &buf, coords)).transpose()
+  }
+
+  // whether a created or modified element belongs in the extract: nodes inside
+  // the region or referenced by a stored way, ways with a stored node inside
+  // the region, and relations with a stored member way or relation or a member
+  // node in it
+  pub(crate) async fn in_extract(&self, dataset: &o5m_stream::Dataset) -> Result<bool,Error> {
+    let region = match &self.region {
+      Some(r) => r,
+      None => return Ok(true),
+    };
+    Ok(match dataset {
+      o5m_stream::Dataset::Node(node) => match &node.data {
+        Some(data) => {
+          region.contains(from_fixed(data.longitude), from_fixed(data.latitude))
+            || !self.get_backrefs(node.id*3+0).await?.is_empty()
+        },
+        None => true,
+      },
+      o5m_stream::Dataset::Way(way) => match &way.data {
+        Some(data) => {
+          let mut deps = HashMap::with_capacity(data.refs.len());
+          self.get_way_deps(data.refs.iter(), &mut deps).await?;
+          deps.values().any(|(lon,lat)| region.contains(*lon as f64, *lat as f64))
+        },
+        None => true,
+      },
+      o5m_stream::Dataset::Relation(relation) => match &relation.data {
+        Some(data) => {
+          let (mut node_refs, mut way_refs) = (vec![],vec![]);
+          for m in data.members.iter() {
+            match m.element_type {
+              o5m_stream::ElementType::Node() => node_refs.push(m.id),
+              o5m_stream::ElementType::Way() => way_refs.push(m.id),
+              o5m_stream::ElementType::Relation() => {
+                let key = Key::from(&id_key(m.id*3+2)?);
+                if self.lstore.lock().await.get(&key)?.is_some() { return Ok(true) }
+              },
+            }
+          }
+          let mut node_deps = HashMap::new();
+          let mut way_deps = HashMap::new();
+          self.get_relation_deps(way_refs.iter(), &mut node_deps, &mut way_deps).await?;
+          let mut member_nodes = HashMap::new();
+          self.get_way_deps(node_refs.iter(), &mut member_nodes).await?;
+          !way_deps.is_empty()
+            || member_nodes.values().any(|(lon,lat)| region.contains(*lon as f64, *lat as f64))
+        },
+        None => true,
+      },
+      _ => true,
+    })
+  }
+
+  // remove a record and everything stored alongside it
+  async fn remove_record(&self, ex_id: u64) -> Result<(),Error> {
+    if let (0,Some(n)) = (ex_id%3,&self.nodes) {
+      n.write().await.remove(ex_id/3);
+    }
+    let mut lstore = self.lstore.lock().await;
+    lstore.del(Key::from(&id_key(ex_id)?))?;
+    lstore.del(Key::from(&tags_key(ex_id)?))?;
+    lstore.del(Key::from(&info_key(ex_id)?))?;
+    lstore.del(Key::from(&members_key(ex_id)?))?;
+    drop(lstore);
+    if let Some(f) = self.reporter.lock().await.as_mut() {
+      f(Phase::Extract(), Ok(()));
+    }
+    Ok(())
+  }
+}
+
+// records of one element type (0 node, 1 way, 2 relation) in key order
+pub(crate) fn records(db: &Database<Key>, t: u64) -> impl Iterator<Item=(Key,Vec<u8>)>+'_ {
+  let lt = Key::from(&[ID_PREFIX+1]);
+  let iter = db.iter(ReadOptions::new());
+  iter.seek(&Key::from(&[ID_PREFIX]));
+  iter.take_while(move |(k,_)| *k < lt)
+    .filter(move |(k,_)| varint::decode(&k.data[1..]).map(|(_,ex_id)| ex_id%3 == t).unwrap_or(false))
+}
+
+// the ex_ids and marks under EXTRACT_PREFIX
+fn marks(db: &Database<Key>) -> impl Iterator<Item=(u64,u8)>+'_ {
+  let lt = Key::from(&[EXTRACT_PREFIX+1]);
+  let iter = db.iter(ReadOptions::new());
+  iter.seek(&Key::from(&[EXTRACT_PREFIX]));
+  iter.take_while(move |(k,_)| *k < lt)
+    .filter_map(|(k,v)| Some((varint::decode(&k.data[1..]).ok()?.1, *v.first()?)))
+}
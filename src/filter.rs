@@ -0,0 +1,114 @@
+use crate::{Error,error::IngestErrorKind as E};
+
+// tag filter rules in the style of osmium tags-filter, one expression each:
+//   [!][types/]key[=value[,value...]]  or  [!][types/]key!=value[,value...]
+// types is any of n, w, and r (default: all three), ! makes the rule an
+// exclusion, and a trailing * in a key or value matches any suffix.
+// an element passes when it matches an include rule for its type (or there
+// are none) and no exclude rule
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Filter {
+  rules: Vec<Rule>,
+}
+
+// also the tag patterns of a type mapping (see mapping)
+#[derive(Debug,Clone,PartialEq)]
+pub(crate) struct Rule {
+  pub(crate) exclude: bool,
+  types: [bool;3], // node, way, relation
+  key: String,
+  values: Option<(bool,Vec<String>)>, // (equal, values)
+}
+
+impl Filter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn push(&mut self, expr: &str) -> Result<(),Error> {
+    self.rules.push(Rule::parse(expr)?);
+    Ok(())
+  }
+
+  // one expression per line. blank lines and lines starting with # are skipped
+  pub fn parse(src: &str) -> Result<Self,Error> {
+    let mut filter = Self::new();
+    for line in src.lines().map(|line| line.trim()) {
+      if line.is_empty() || line.starts_with('#') { continue }
+      filter.push(line)?;
+    }
+    Ok(filter)
+  }
+
+  pub fn from_path(path: &std::path::Path) -> Result<Self,Error> {
+    Self::parse(&std::fs::read_to_string(path)?)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.rules.is_empty()
+  }
+
+  // t is the element type: 0 for nodes, 1 for ways, 2 for relations
+  pub fn matches(&self, t: u64, tags: &[(&str,&str)]) -> bool {
+    let mut includes = self.rules.iter().filter(|r| !r.exclude).peekable();
+    let included = includes.peek().is_none() || includes.any(|r| r.matches(t, tags));
+    included && !self.rules.iter().any(|r| r.exclude && r.matches(t, tags))
+  }
+}
+
+impl Rule {
+  pub(crate) fn parse(expr: &str) -> Result<Self,Error> {
+    let s = expr.trim();
+    let (exclude,s) = match s.strip_prefix('!') {
+      Some(rest) => (true,rest),
+      None => (false,s),
+    };
+    let (types,s) = match s.find('/') {
+      Some(i) if i > 0 && s[..i].chars().all(|c| "nwr".contains(c)) => {
+        let t = &s[..i];
+        ([t.contains('n'),t.contains('w'),t.contains('r')],&s[i+1..])
+      },
+      _ => ([true;3],s),
+    };
+    let (key,values) = if let Some(i) = s.find("!=") {
+      (&s[..i],Some((false,&s[i+2..])))
+    } else if let Some(i) = s.find('=') {
+      (&s[..i],Some((true,&s[i+1..])))
+    } else {
+      (s,None)
+    };
+    let values = values.map(|(eq,vs)| {
+      (eq,vs.split(',').map(|v| v.trim().to_string()).collect::<Vec<String>>())
+    });
+    let empty_value = values.as_ref().map(|(_,vs)| vs.iter().any(|v| v.is_empty())).unwrap_or(false);
+    if key.trim().is_empty() || empty_value { E::InvalidFilter { expr: expr.to_string() }.raise()? }
+    Ok(Self { exclude, types, key: key.trim().to_string(), values })
+  }
+
+  pub(crate) fn matches(&self, t: u64, tags: &[(&str,&str)]) -> bool {
+    self.types[(t%3) as usize] && tags.iter().any(|(k,v)| {
+      glob(&self.key, k) && match &self.values {
+        Some((eq,vs)) => vs.iter().any(|p| glob(p, v)) == *eq,
+        None => true,
+      }
+    })
+  }
+}
+
+fn glob(pattern: &str, s: &str) -> bool {
+  match pattern.strip_suffix('*') {
+    Some(prefix) => s.starts_with(prefix),
+    None => pattern == s,
+  }
+}
+
+// the tags to encode an element with: none if the filter rejects it, so the
+// record is still stored for the ways and relations that use it but it gets
+// no feature type or labels of its own
+pub fn filter_tags<'a>(filter: Option<&Filter>, t: u64, tags: Vec<(&'a str,&'a str)>)
+-> Vec<(&'a str,&'a str)> {
+  match filter {
+    Some(f) if !f.matches(t, &tags) => vec![],
+    _ => tags,
+  }
+}
@@ -0,0 +1,144 @@
+use crate::{Error,error::IngestErrorKind as E};
+use desert::varint;
+use unicode_normalization::UnicodeNormalization;
+
+// which labels to keep from the ones georender derives from name tags.
+// each label is "lang=value" where lang is "" for name (and alt_name and the
+// like) and the suffix for name:lang. languages are kept in the order given
+// and "*" stands for every other language. when none of them are present,
+// the first language in the fallback chain that is present is used instead.
+// with no languages every label is kept
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct LabelPolicy {
+  pub languages: Vec<String>,
+  pub fallback: Vec<String>,
+  pub max_labels: Option<usize>,
+  pub normalization: Option<Normalization>,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Normalization {
+  Nfc,
+  Nfkc,
+}
+
+impl Normalization {
+  pub fn from_name(name: &str) -> Result<Self,Error> {
+    Ok(match name.to_lowercase().as_str() {
+      "nfc" => Normalization::Nfc,
+      "nfkc" => Normalization::Nfkc,
+      _ => E::InvalidLabelPolicy {
+        message: format!["unknown normalization form {:?}, expected nfc or nfkc", name]
+      }.raise()?,
+    })
+  }
+
+  pub fn apply(&self, s: &str) -> String {
+    match self {
+      Normalization::Nfc => s.nfc().collect(),
+      Normalization::Nfkc => s.nfkc().collect(),
+    }
+  }
+}
+
+impl LabelPolicy {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // comma-separated languages where "name" stands for the plain name tag,
+  // like "en,de,name"
+  pub fn parse_languages(src: &str) -> Result<Vec<String>,Error> {
+    let mut languages = vec![];
+    for lang in src.split(',').map(|lang| lang.trim()) {
+      if lang.is_empty() {
+        E::InvalidLabelPolicy { message: format!["empty language in {:?}", src] }.raise()?
+      }
+      languages.push(if lang == "name" { String::new() } else { lang.to_string() });
+    }
+    Ok(languages)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self == &Self::default()
+  }
+
+  // apply the policy to encoded labels from georender_pack::tags::parse
+  pub fn select(&self, labels: &[u8]) -> Result<Vec<u8>,Error> {
+    let mut all = decode_labels(labels)?;
+    if let Some(n) = &self.normalization {
+      all = all.iter().map(|label| n.apply(label)).collect();
+    }
+    let mut kept = if self.languages.is_empty() {
+      all.clone()
+    } else {
+      pick(&all, &self.languages)
+    };
+    if kept.is_empty() {
+      for lang in self.fallback.iter() {
+        kept = pick(&all, &[lang.clone()]);
+        if !kept.is_empty() { break }
+      }
+    }
+    if let Some(n) = self.max_labels {
+      kept.truncate(n);
+    }
+    encode_labels(&kept)
+  }
+}
+
+// labels for each language in order, then every label not already picked for "*"
+fn pick(all: &[String], languages: &[String]) -> Vec<String> {
+  let mut kept: Vec<String> = vec![];
+  for lang in languages.iter() {
+    for label in all.iter() {
+      let matches = lang == "*" || label.split('=').next() == Some(lang.as_str());
+      if matches && !kept.contains(label) {
+        kept.push(label.clone());
+      }
+    }
+  }
+  kept
+}
+
+// the labels to encode an element with
+pub fn select_labels(policy: Option<&LabelPolicy>, labels: Vec<u8>) -> Result<Vec<u8>,Error> {
+  match policy {
+    Some(p) => p.select(&labels),
+    None => Ok(labels),
+  }
+}
+
+// length-prefixed strings followed by a zero length
+pub fn decode_labels(buf: &[u8]) -> Result<Vec<String>,Error> {
+  let mut labels = vec![];
+  let mut offset = 0;
+  while offset < buf.len() {
+    let (s,n) = varint::decode(&buf[offset..])?;
+    offset += s;
+    if n == 0 { break }
+    let label = match buf.get(offset..).and_then(|b| b.get(..n as usize)) {
+      Some(label) => label,
+      None => E::InvalidLabels {
+        message: format!["label of {} bytes at offset {} runs past the end of {} bytes", n, offset, buf.len()]
+      }.raise()?,
+    };
+    labels.push(String::from_utf8(label.to_vec())?);
+    offset += n as usize;
+  }
+  Ok(labels)
+}
+
+pub fn encode_labels(labels: &[String]) -> Result<Vec<u8>,Error> {
+  let size = 1 + labels.iter().fold(0usize,|sum,label| {
+    sum + varint::length(label.len() as u64) + label.len()
+  });
+  let mut buf = vec![0u8;size];
+  let mut offset = 0;
+  for label in labels.iter() {
+    offset += varint::encode(label.len() as u64, &mut buf[offset..])?;
+    buf[offset..offset+label.len()].copy_from_slice(label.as_bytes());
+    offset += label.len();
+  }
+  Ok(buf)
+}
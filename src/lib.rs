@@ -5,16 +5,39 @@ pub mod store;
 pub use store::*;
 pub mod error;
 pub use error::*;
+pub mod xml;
+pub mod decompress;
+pub use decompress::*;
+pub mod replicate;
+pub mod meta;
+pub mod migrate;
+pub mod verify;
+pub mod nodes;
+pub mod extract;
+pub mod filter;
+pub mod mapping;
+pub mod label;
+pub mod multipolygon;
+pub mod coastline;
+pub use nodes::NodeCache;
 
 pub const BACKREF_PREFIX: u8 = 1;
 pub const REF_PREFIX: u8 = 2;
+pub const META_PREFIX: u8 = 3;
+pub const TAGS_PREFIX: u8 = 4;
+pub const INFO_PREFIX: u8 = 5;
+pub const MEMBERS_PREFIX: u8 = 6;
+pub const EXTRACT_PREFIX: u8 = 7;
 
 use std::collections::{HashMap,HashSet};
-use async_std::{prelude::*,sync::{Arc,Mutex},io};
+use async_std::{prelude::*,sync::{Arc,Mutex,RwLock},io};
 use desert::varint;
+use error::IngestErrorKind as E;
 
 use leveldb::iterator::{Iterable,LevelDBIterator};
 use leveldb::options::ReadOptions;
+use leveldb::snapshots::{Snapshot,Snapshots};
+use rayon::iter::{IntoParallelIterator,ParallelBridge,ParallelIterator};
 
 type NodeDeps = HashMap<u64,(f32,f32)>;
 type WayDeps = HashMap<u64,Vec<u64>>;
@@ -24,16 +47,32 @@ type P = (eyros::Coord<f32>,eyros::Coord<f32>);
 #[derive(Debug,Clone,PartialEq)]
 pub enum Phase {
   Pbf(),
+  Xml(),
+  O5m(),
   Process(),
   Changeset(),
+  Replicate(),
+  Migrate(),
+  Verify(),
+  Backrefs(),
+  Extract(),
+  Coastline(),
 }
 
 impl ToString for Phase {
   fn to_string(&self) -> String {
     match self {
       Phase::Pbf() => "pbf",
+      Phase::Xml() => "xml",
+      Phase::O5m() => "o5m",
       Phase::Process() => "process",
       Phase::Changeset() => "changeset",
+      Phase::Replicate() => "replicate",
+      Phase::Migrate() => "migrate",
+      Phase::Verify() => "verify",
+      Phase::Backrefs() => "backrefs",
+      Phase::Extract() => "extract",
+      Phase::Coastline() => "coastline",
     }.to_string()
   }
 }
@@ -42,6 +81,15 @@ pub struct Ingest {
   pub lstore: Arc<Mutex<LStore>>,
   pub estore: Arc<Mutex<EStore>>,
   place_other: u64,
+  threads: usize,
+  nodes: Option<Arc<RwLock<NodeCache>>>,
+  schema_version: Option<u64>,
+  raw_tags: bool,
+  element_info: bool,
+  region: Option<extract::Region>,
+  filter: Option<filter::Filter>,
+  mapping: Option<mapping::Mapping>,
+  label_policy: Option<label::LabelPolicy>,
   reporter: Arc<Mutex<Option<Box<dyn FnMut(Phase, Result<(),Error>) -> ()+Send+Sync>>>>,
 }
 
@@ -51,69 +99,354 @@ impl Ingest {
       lstore: Arc::new(Mutex::new(lstore)),
       estore: Arc::new(Mutex::new(estore)),
       place_other: *georender_pack::osm_types::get_types().get("place.other").unwrap(),
+      threads: 0,
+      nodes: None,
+      schema_version: None,
+      raw_tags: false,
+      element_info: false,
+      region: None,
+      filter: None,
+      mapping: None,
+      label_policy: None,
       reporter: Arc::new(Mutex::new(None)),
     }
   }
 
+  // number of worker threads for decoding pbf blobs and for the process phase.
+  // 0 uses one per cpu
+  pub fn threads(mut self, n: usize) -> Self {
+    self.threads = n;
+    self
+  }
+
+  // look up node locations in a dense cache before leveldb. the cache is filled
+  // as records are loaded and kept current by changesets, so the same file
+  // should be used for every phase
+  pub fn node_cache(mut self, nodes: NodeCache) -> Self {
+    self.nodes = Some(Arc::new(RwLock::new(nodes)));
+    self
+  }
+
+  // schema version for a new level db. existing databases keep their version
+  // and refuse to open if it differs, so use migrate to change it
+  pub fn schema_version(mut self, version: u64) -> Self {
+    self.schema_version = Some(version);
+    self
+  }
+
+  // store the raw tags of every element under TAGS_PREFIX when loading and
+  // applying changesets. process derives the feature type and labels from
+  // them again, so a new tag mapping only needs process to be re-run
+  pub fn raw_tags(mut self, raw_tags: bool) -> Self {
+    self.raw_tags = raw_tags;
+    self
+  }
+
+  // store the version, timestamp, changeset, and uid of every element under
+  // INFO_PREFIX. changesets skip changes that are not newer than the stored
+  // version, so overlapping diffs can be applied more than once
+  pub fn element_info(mut self, element_info: bool) -> Self {
+    self.element_info = element_info;
+    self
+  }
+
+  // only keep the part of the input inside a region: loads drop everything
+  // else before process (see extract), changesets skip elements outside of it,
+  // and nodes outside of it are only kept as parts of ways, not as points
+  pub fn region(mut self, region: extract::Region) -> Self {
+    self.region = Some(region);
+    self
+  }
+
+  pub fn bbox(self, bbox: extract::BBox) -> Self {
+    self.region(extract::Region::Bbox(bbox))
+  }
+
+  // only give a feature type and labels to elements whose tags pass the filter.
+  // other elements are still stored for the ways and relations that use them.
+  // with raw tags, process applies the filter again
+  pub fn filter(mut self, filter: filter::Filter) -> Self {
+    self.filter = Some(filter);
+    self
+  }
+
+  // classify elements with a type mapping on top of georender's types. the
+  // type of unmatched elements in the mapping replaces place.other as the one
+  // that process skips, so the same mapping should be used for every phase
+  pub fn mapping(mut self, mapping: mapping::Mapping) -> Self {
+    self.place_other = mapping.other();
+    self.mapping = Some(mapping);
+    self
+  }
+
+  // which of the name labels to store and how to normalize them. with raw
+  // tags, process applies its own policy to them again
+  pub fn label_policy(mut self, policy: label::LabelPolicy) -> Self {
+    self.label_policy = Some(policy);
+    self
+  }
+
   pub fn reporter(mut self, f: Box<dyn FnMut(Phase, Result<(),Error>) -> ()+Send+Sync>) -> Self {
     self.reporter = Arc::new(Mutex::new(Some(f)));
     self
   }
 
-  // write the pbf into leveldb
+  // write the pbf into leveldb. blobs are decoded and encoded on a pool of
+  // self.threads workers and written in key order within each blob
   pub async fn load_pbf<R: std::io::Read+Send>(&mut self, pbf: R) -> Result<(),Error> {
     let mut lstore = self.lstore.lock().await;
     let mut reporter = self.reporter.lock().await;
-    osmpbf::ElementReader::new(pbf).for_each(|element| {
-      let res = encode_osmpbf(&element);
+    let mut nodes = match &self.nodes {
+      Some(n) => Some(n.write().await),
+      None => None,
+    };
+    let mut pbf = decompress(std::io::BufReader::new(pbf))?;
+    let (header,consumed) = meta::PbfHeader::read(&mut pbf)?;
+    let pbf = std::io::Read::chain(std::io::Cursor::new(consumed), pbf);
+    let blobs = osmpbf::BlobReader::new(pbf);
+    let coords = self.coords();
+    let (raw_tags,element_info) = (self.raw_tags,self.element_info);
+    let filter = self.filter.clone();
+    let mapping = self.mapping.clone();
+    let policy = self.label_policy.clone();
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(self.threads).build()?;
+    let (tx,rx) = std::sync::mpsc::sync_channel(pool.current_num_threads()*2);
+    let mut res = Ok(());
+    pool.in_place_scope(|s| {
+      s.spawn(move |_| {
+        let opts = EncodeOptions {
+          coords,
+          raw_tags,
+          element_info,
+          filter: filter.as_ref(),
+          mapping: mapping.as_ref(),
+          policy: policy.as_ref(),
+        };
+        blobs.par_bridge().for_each_with(tx, |tx, blob| {
+          tx.send(blob.map_err(|e| e.into()).and_then(|blob| encode_blob(blob, &opts))).unwrap_or(());
+        });
+      });
+      for records in rx.iter() {
+        let records = match records {
+          Ok(r) => r,
+          Err(e) => {
+            // keep draining so the decoding workers can finish
+            if res.is_ok() { res = Err(e) }
+            continue;
+          },
+        };
+        if res.is_err() { continue }
+        for record in records {
+          match (record, reporter.as_mut()) {
+            (Err(e),Some(f)) => f(Phase::Pbf(),Err(e)),
+            (Err(_),_) => {},
+            (Ok((key,value)),Some(f)) => {
+              if let Err(e) = put_record(&mut lstore, nodes.as_deref_mut(), coords, &key, &value) {
+                f(Phase::Pbf(),Err(e));
+              } else {
+                f(Phase::Pbf(),Ok(()));
+              }
+            },
+            (Ok((key,value)),None) => {
+              if let Err(_) = put_record(&mut lstore, nodes.as_deref_mut(), coords, &key, &value) {}
+            }
+          }
+        }
+      }
+    });
+    res?;
+    write_header(&mut lstore, &Phase::Pbf(), self.get_schema_version())?;
+    if let Some(h) = header {
+      write_pbf_header(&mut lstore, &h)?;
+    }
+    lstore.flush()?;
+    if let Some(n) = nodes { n.flush()? }
+    drop((lstore,reporter));
+    self.extract().await
+  }
+
+  // write osm xml into leveldb
+  pub async fn load_xml<R: std::io::BufRead+Send>(&mut self, xml: R) -> Result<(),Error> {
+    let mut lstore = self.lstore.lock().await;
+    let mut reporter = self.reporter.lock().await;
+    let mut nodes = match &self.nodes {
+      Some(n) => Some(n.write().await),
+      None => None,
+    };
+    let opts = self.encode_options();
+    let coords = opts.coords;
+    for result in xml::Reader::new(decompress(xml)?) {
+      let xml_element = result?;
+      let element = Element::from_xml(&xml_element);
+      if opts.raw_tags {
+        let (key,value) = encode_element_tags(&element)?;
+        lstore.put(Key::from(&key), &value)?;
+      }
+      if opts.element_info {
+        if let Some((key,value)) = encode_element_info(&element)? {
+          lstore.put(Key::from(&key), &value)?;
+        }
+      }
+      if let Some((key,value)) = encode_element_members(&element)? {
+        lstore.put(Key::from(&key), &value)?;
+      }
+      let res = encode_element(&element, &opts);
+      match (res, reporter.as_mut()) {
+        (Err(e),Some(f)) => f(Phase::Xml(),Err(e.into())),
+        (Err(_),_) => {},
+        (Ok((key,value)),Some(f)) => {
+          if let Err(e) = put_record(&mut lstore, nodes.as_deref_mut(), coords, &key, &value) {
+            f(Phase::Xml(),Err(e.into()));
+          } else {
+            f(Phase::Xml(),Ok(()));
+          }
+        },
+        (Ok((key,value)),None) => {
+          if let Err(_) = put_record(&mut lstore, nodes.as_deref_mut(), coords, &key, &value) {}
+        }
+      }
+    }
+    write_header(&mut lstore, &Phase::Xml(), self.get_schema_version())?;
+    lstore.flush()?;
+    if let Some(n) = nodes { n.flush()? }
+    drop((lstore,reporter));
+    self.extract().await
+  }
+
+  // write a complete o5m file into leveldb
+  pub async fn load_o5m(&mut self, infile: Box<dyn io::Read+Unpin>) -> Result<(),Error> {
+    let mut lstore = self.lstore.lock().await;
+    let mut reporter = self.reporter.lock().await;
+    let mut nodes = match &self.nodes {
+      Some(n) => Some(n.write().await),
+      None => None,
+    };
+    let opts = self.encode_options();
+    let coords = opts.coords;
+    let mut stream = o5m_stream::decode(decompress_async(infile).await?);
+    while let Some(result) = stream.next().await {
+      let dataset = result?;
+      let element = match Element::from_o5m(&dataset) {
+        Some(element) => element,
+        None => continue,
+      };
+      if opts.raw_tags {
+        let (key,value) = encode_element_tags(&element)?;
+        lstore.put(Key::from(&key), &value)?;
+      }
+      if opts.element_info {
+        if let Some((key,value)) = encode_element_info(&element)? {
+          lstore.put(Key::from(&key), &value)?;
+        }
+      }
+      if let Some((key,value)) = encode_element_members(&element)? {
+        lstore.put(Key::from(&key), &value)?;
+      }
+      let res = encode_element(&element, &opts);
       match (res, reporter.as_mut()) {
-        (Err(e),Some(f)) => f(Phase::Pbf(),Err(e.into())),
+        (Err(e),Some(f)) => f(Phase::O5m(),Err(e.into())),
         (Err(_),_) => {},
         (Ok((key,value)),Some(f)) => {
-          if let Err(e) = lstore.put(Key::from(&key), &value) {
-            f(Phase::Pbf(),Err(e.into()));
+          if let Err(e) = put_record(&mut lstore, nodes.as_deref_mut(), coords, &key, &value) {
+            f(Phase::O5m(),Err(e.into()));
           } else {
-            f(Phase::Pbf(),Ok(()));
+            f(Phase::O5m(),Ok(()));
           }
         },
         (Ok((key,value)),None) => {
-          if let Err(_) = lstore.put(Key::from(&key), &value) {}
+          if let Err(_) = put_record(&mut lstore, nodes.as_deref_mut(), coords, &key, &value) {}
         }
       }
-    })?;
+    }
+    write_header(&mut lstore, &Phase::O5m(), self.get_schema_version())?;
     lstore.flush()?;
-    Ok(())
+    if let Some(n) = nodes { n.flush()? }
+    drop((lstore,reporter));
+    self.extract().await
   }
 
   // loop over the db, denormalize the records, georender-pack the data,
-  // store into eyros, and write backrefs into leveldb
+  // store into eyros, and write backrefs into leveldb.
+  // the keyspace is split on the first byte of the id varint into ranges that
+  // are encoded concurrently on self.threads workers, which all read from one
+  // leveldb snapshot taken after the pending writes are flushed.
+  // records retagged from raw tags are written back, but only their feature
+  // type, labels, and area flag change and no other record's row depends on those
   pub async fn process(&mut self) -> () {
-    let gt = Key::from(&vec![ID_PREFIX]);
-    let lt = Key::from(&vec![ID_PREFIX,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff]);
     let db = {
-      let lstore = self.lstore.lock().await;
+      let mut lstore = self.lstore.lock().await;
+      if let Err(e) = lstore.flush() {
+        if let Some(f) = self.reporter.lock().await.as_mut() {
+          f(Phase::Process(), Err(e));
+        }
+      }
       lstore.db.clone()
     };
-    let mut iter = {
-      let i = db.iter(ReadOptions::new());
-      i.seek(&gt);
-      i.take_while(move |(k,_)| *k < lt)
+    let place_other = self.place_other;
+    let coords = self.coords();
+    let nodes = self.nodes.clone();
+    let region = self.region.clone();
+    let filter = self.filter.clone();
+    let mapping = self.mapping.clone();
+    let policy = self.label_policy.clone();
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(self.threads).build() {
+      Ok(pool) => pool,
+      Err(e) => {
+        if let Some(f) = self.reporter.lock().await.as_mut() {
+          f(Phase::Process(), Err(e.into()));
+        }
+        return;
+      },
     };
-    while let Some((key,value)) = iter.next() {
+    let (tx,rx) = async_std::channel::bounded(pool.current_num_threads()*1024);
+    let workers = std::thread::spawn(move || {
+      let snapshot = SharedSnapshot(db.snapshot());
+      let snapshot = &snapshot;
+      pool.install(|| {
+        (0..=255u8).into_par_iter().for_each_with(tx, |tx, b| {
+          let gt = Key::from(&[ID_PREFIX,b]);
+          let lt = match b {
+            255 => Key::from(&[ID_PREFIX+1]),
+            _ => Key::from(&[ID_PREFIX,b+1]),
+          };
+          let mut get = |key: &Key| -> Result<Option<Vec<u8>>,Error> {
+            Ok(snapshot.0.get(ReadOptions::new(), key)?)
+          };
+          // the read lock is only held for each lookup
+          let get_node = |id: u64| async_std::task::block_on(nodes.as_ref()?.read()).get(id,coords);
+          let iter = snapshot.0.iter(ReadOptions::new());
+          iter.seek(&gt);
+          for (key,value) in iter.take_while(|(k,_)| *k < lt) {
+            let res = decode(&key.data,&value,coords)
+              .and_then(|decoded| retag_record(&mut get, coords, filter.as_ref(), mapping.as_ref(),
+                policy.as_ref(), decoded))
+              .and_then(|(decoded,record)| {
+                let (row,backrefs,problem) = record_row(&mut get, &get_node, coords, place_other, region.as_ref(), &decoded)?;
+                Ok((record,row,backrefs,problem))
+              });
+            if async_std::task::block_on(tx.send(res)).is_err() { return }
+          }
+        });
+      });
+    });
+    while let Ok(res) = rx.recv().await {
       // presumably, if a feature doesn't have distinguishing tags,
       // it could be referred to by other geometry, so skip it
       // to save space in the final output
-      let res = match decode(&key.data,&value) {
-        Err(e) => Err(e.into()),
-        Ok(Decoded::Node(node)) => self.create_node(&node).await,
-        Ok(Decoded::Way(way)) => self.create_way(&way).await,
-        Ok(Decoded::Relation(relation)) => self.create_relation(&relation).await,
+      let res = match res {
+        Ok((record,row,backrefs,problem)) => match self.write_record(record).await {
+          Ok(()) => self.write_row(row, backrefs).await.and(problem.map_or(Ok(()), Err)),
+          Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
       };
       if let Some(f) = self.reporter.lock().await.as_mut() {
-        match res {
-          Err(e) => f(Phase::Process(), Err(e.into())),
-          Ok(r) => f(Phase::Process(), Ok(r)),
-        }
+        f(Phase::Process(), res);
+      }
+    }
+    if let Err(_) = workers.join() {
+      if let Some(f) = self.reporter.lock().await.as_mut() {
+        f(Phase::Process(), Err("process worker panicked".into()));
       }
     }
     {
@@ -138,10 +471,14 @@ impl Ingest {
     {
       let mut lstore = self.lstore.lock().await;
       if let Some(f) = self.reporter.lock().await.as_mut() {
+        if let Err(e) = write_header(&mut lstore, &Phase::Process(), self.get_schema_version()) {
+          f(Phase::Process(), Err(e.into()));
+        }
         if let Err(e) = lstore.flush() {
           f(Phase::Process(), Err(e.into()));
         }
       } else {
+        if let Err(_) = write_header(&mut lstore, &Phase::Process(), self.get_schema_version()) {}
         if let Err(_) = lstore.flush() {}
       }
     }
@@ -149,106 +486,312 @@ impl Ingest {
 
   // import changes from an o5c changeset file
   pub async fn changeset(&mut self, infile: Box<dyn io::Read+Unpin>) -> Result<(),Error> {
-    let mut stream = o5m_stream::decode(infile);
+    let mut stream = o5m_stream::decode(decompress_async(infile).await?);
     while let Some(result) = stream.next().await {
-      let dataset = result?;
-      let m = match &dataset {
-        o5m_stream::Dataset::Node(node) => {
-          match &node.data {
-            Some(_) => Some((false,node.id*3+0)), // modify or create
-            None => Some((true,node.id*3+0)), // delete
-          }
-        },
-        o5m_stream::Dataset::Way(way) => {
-          match &way.data {
-            Some(data) => { // modify or create
-              let mut deps = HashMap::with_capacity(data.refs.len());
-              self.get_way_deps(data.refs.iter(), &mut deps).await?;
-              let refs = self.get_refs(way.id*3+1).await?;
-              let prev_set = refs.into_iter().collect::<HashSet<u64>>();
-              let new_set = deps.keys().map(|r| *r).into_iter().collect::<HashSet<u64>>();
-              {
-                let mut lstore = self.lstore.lock().await;
-                for r in prev_set.difference(&new_set) {
-                  lstore.del(Key::from(&backref_key(*r*3+0, way.id*3+1)?))?;
-                }
-                for r in new_set.difference(&prev_set) {
-                  lstore.put(Key::from(&backref_key(*r*3+0, way.id*3+1)?),&vec![])?;
-                }
+      self.apply_change(&result?).await?;
+    }
+    self.flush_changeset().await
+  }
+
+  // import changes from an osmChange (osc) xml file
+  pub async fn changeset_osc<R: std::io::BufRead+Send>(&mut self, infile: R) -> Result<(),Error> {
+    let mut reader = xml::Reader::new(decompress(infile)?);
+    while let Some((action,element)) = reader.next_change()? {
+      self.apply_change(&element.into_dataset(&action)).await?;
+    }
+    self.flush_changeset().await
+  }
+
+  // a dataset without data is a delete, otherwise a create or modify
+  async fn apply_change(&mut self, dataset: &o5m_stream::Dataset) -> Result<(),Error> {
+    let info = dataset.get_info().map(|i| ElementInfo::from_o5m(&i)).unwrap_or_default();
+    let change_id = match dataset {
+      o5m_stream::Dataset::Node(node) => node.id*3+0,
+      o5m_stream::Dataset::Way(way) => way.id*3+1,
+      o5m_stream::Dataset::Relation(relation) => relation.id*3+2,
+      _ => return Ok(()),
+    };
+    // skip changes that are not newer than what was already applied,
+    // such as the same diff applied twice
+    let stored = self.lstore.lock().await.get(&Key::from(&info_key(change_id)?))?;
+    if let Some(buf) = stored {
+      if !info.supersedes(&decode_info(&buf)?) { return Ok(()) }
+    }
+    // creates and modifies outside of the extract become deletes,
+    // which are skipped entirely for elements that aren't stored
+    let keep = self.in_extract(dataset).await?;
+    if !keep && self.lstore.lock().await.get(&Key::from(&id_key(change_id)?))?.is_none() {
+      return Ok(());
+    }
+    let m = match dataset {
+      o5m_stream::Dataset::Node(node) => {
+        match &node.data {
+          Some(_) if keep => Some((false,node.id*3+0)), // modify or create
+          _ => Some((true,node.id*3+0)), // delete
+        }
+      },
+      o5m_stream::Dataset::Way(way) => {
+        match &way.data {
+          Some(data) if keep => { // modify or create
+            let mut deps = HashMap::with_capacity(data.refs.len());
+            self.get_way_deps(data.refs.iter(), &mut deps).await?;
+            let refs = self.get_refs(way.id*3+1).await?;
+            let prev_set = refs.into_iter().collect::<HashSet<u64>>();
+            let new_set = deps.keys().map(|r| *r).into_iter().collect::<HashSet<u64>>();
+            {
+              let mut lstore = self.lstore.lock().await;
+              for r in prev_set.difference(&new_set) {
+                lstore.del(Key::from(&backref_key(*r*3+0, way.id*3+1)?))?;
               }
-              Some((false,way.id*3+1))
-            },
-            None => Some((true,way.id*3+1)), // delete
-          }
-        },
-        o5m_stream::Dataset::Relation(relation) => {
-          match &relation.data {
-            Some(data) => { // modify or create
-              let mut node_deps = HashMap::new();
-              let mut way_deps = HashMap::with_capacity(data.members.len());
-              let refs = data.members.iter().map(|m| m.id).collect::<Vec<u64>>();
-              self.get_relation_deps(refs.iter(), &mut node_deps, &mut way_deps).await?;
-              let prev_refs = self.get_refs(relation.id*3+2).await?;
-              let prev_set = prev_refs.into_iter().collect::<HashSet<u64>>();
-              let new_set = way_deps.keys().map(|r| *r).into_iter().collect::<HashSet<u64>>();
-              {
-                let mut lstore = self.lstore.lock().await;
-                for r in prev_set.difference(&new_set) {
-                  lstore.del(Key::from(&backref_key(*r*3+1, relation.id*3+2)?))?;
-                }
-                for r in new_set.difference(&prev_set) {
-                  lstore.put(Key::from(&backref_key(*r*3+1, relation.id*3+2)?),&vec![])?;
-                }
+              for r in new_set.difference(&prev_set) {
+                lstore.put(Key::from(&backref_key(*r*3+0, way.id*3+1)?),&vec![])?;
               }
-              Some((false,relation.id*3+2))
-            },
-            None => Some((true,relation.id*3+2)), // delete
-          }
-        },
-        _ => None,
-      };
-      if let Some((is_rm,ex_id)) = m {
-        if is_rm {
-          // delete the record but don't bother dealing with backrefs
-          // because the referred-to elements *should* be deleted too.
-          // not the job of this ingest script to verify changeset integrity
-          let backrefs = self.get_backrefs(ex_id).await?;
-          if let Some(pt) = self.get_point(ex_id).await? {
-            let mut estore = self.estore.lock().await;
-            estore.delete(pt, ex_id).await?;
-          }
+            }
+            Some((false,way.id*3+1))
+          },
+          _ => Some((true,way.id*3+1)), // delete
+        }
+      },
+      o5m_stream::Dataset::Relation(relation) => {
+        match &relation.data {
+          Some(data) if keep => { // modify or create
+            let mut node_deps = HashMap::new();
+            let mut way_deps = HashMap::with_capacity(data.members.len());
+            let refs = data.members.iter().map(|m| m.id).collect::<Vec<u64>>();
+            self.get_relation_deps(refs.iter(), &mut node_deps, &mut way_deps).await?;
+            let prev_refs = self.get_refs(relation.id*3+2).await?;
+            let prev_set = prev_refs.into_iter().collect::<HashSet<u64>>();
+            let new_set = way_deps.keys().map(|r| *r).into_iter().collect::<HashSet<u64>>();
+            // node and relation members (and the ways of routes) are kept as extended ids
+            let prev_members = self.get_member_refs(relation.id*3+2).await?
+              .into_iter().collect::<HashSet<u64>>();
+            let route = is_route(&relation.tags.iter()
+              .map(|(k,v)| (k.as_str(),v.as_str())).collect::<Vec<_>>());
+            let mut new_members = HashSet::new();
+            for m in data.members.iter() {
+              let r = match m.element_type {
+                o5m_stream::ElementType::Node() => m.id*3+0,
+                o5m_stream::ElementType::Way() if route => m.id*3+1,
+                o5m_stream::ElementType::Relation() => m.id*3+2,
+                _ => continue,
+              };
+              if self.lstore.lock().await.get(&Key::from(&id_key(r)?))?.is_some() {
+                new_members.insert(r);
+              }
+            }
+            {
+              let mut lstore = self.lstore.lock().await;
+              for r in prev_set.difference(&new_set) {
+                lstore.del(Key::from(&backref_key(*r*3+1, relation.id*3+2)?))?;
+              }
+              for r in new_set.difference(&prev_set) {
+                lstore.put(Key::from(&backref_key(*r*3+1, relation.id*3+2)?),&vec![])?;
+              }
+              for r in prev_members.difference(&new_members) {
+                lstore.del(Key::from(&backref_key(*r, relation.id*3+2)?))?;
+              }
+              for r in new_members.difference(&prev_members) {
+                lstore.put(Key::from(&backref_key(*r, relation.id*3+2)?),&vec![])?;
+              }
+            }
+            Some((false,relation.id*3+2))
+          },
+          _ => Some((true,relation.id*3+2)), // delete
+        }
+      },
+      _ => None,
+    };
+    if let Some((is_rm,ex_id)) = m {
+      if is_rm {
+        // delete the record but don't bother dealing with backrefs
+        // because the referred-to elements *should* be deleted too.
+        // not the job of this ingest script to verify changeset integrity
+        let backrefs = self.get_backrefs(ex_id).await?;
+        // an element that left the extract leaves its members behind,
+        // so drop their backrefs to it
+        let refs = if keep { vec![] } else { self.get_refs(ex_id).await? };
+        let member_refs = if keep { vec![] } else { self.get_member_refs(ex_id).await? };
+        if let Some(pt) = self.get_point(ex_id).await? {
+          let mut estore = self.estore.lock().await;
+          estore.delete(pt, ex_id).await?;
+        }
+        if let (0,Some(n)) = (ex_id%3,&self.nodes) {
+          n.write().await.remove(ex_id/3);
+        }
+        let mut lstore = self.lstore.lock().await;
+        lstore.del(Key::from(&id_key(ex_id)?))?;
+        lstore.del(Key::from(&tags_key(ex_id)?))?;
+        lstore.del(Key::from(&members_key(ex_id)?))?;
+        for r in backrefs.iter() {
+          lstore.del(Key::from(&backref_key(*r, ex_id)?))?;
+        }
+        for r in refs.iter() {
+          lstore.del(Key::from(&backref_key(*r*3 + ex_id%3 - 1, ex_id)?))?;
+        }
+        for r in member_refs.iter() {
+          lstore.del(Key::from(&backref_key(*r, ex_id)?))?;
+        }
+      } else {
+        let prev_pt = self.get_point(ex_id).await?;
+        if let Some(element) = Element::from_o5m(dataset) {
+          let (key,value) = encode_element(&element, &self.encode_options())?;
+          let mut nodes = match &self.nodes {
+            Some(n) => Some(n.write().await),
+            None => None,
+          };
           let mut lstore = self.lstore.lock().await;
-          lstore.del(Key::from(&id_key(ex_id)?))?;
-          for r in backrefs.iter() {
-            lstore.del(Key::from(&backref_key(*r, ex_id)?))?;
+          put_record(&mut lstore, nodes.as_deref_mut(), self.coords(), &key, &value)?;
+          // drop stale tags when raw tags aren't kept so process doesn't use them
+          let (key,value) = encode_element_tags(&element)?;
+          if self.raw_tags {
+            lstore.put(Key::from(&key), &value)?;
+          } else {
+            lstore.del(Key::from(&key))?;
           }
-        } else {
-          let prev_pt = self.get_point(ex_id).await?;
-          if let Some(encoded) = encode_o5m(&dataset)? {
-            let mut lstore = self.lstore.lock().await;
-            lstore.put(Key::from(&id_key(ex_id)?), &encoded)?;
+          match encode_element_members(&element)? {
+            Some((key,value)) => lstore.put(Key::from(&key), &value)?,
+            None if ex_id%3 == 2 => lstore.del(Key::from(&members_key(ex_id)?))?,
+            None => {},
           }
-          // recursively recalculates backrefs
-          self.recalculate(ex_id, &prev_pt).await?;
-          self.estore.lock().await.check_flush().await?;
         }
+        // recursively recalculates backrefs
+        self.recalculate(ex_id, &prev_pt, &mut HashSet::new()).await?;
+        self.estore.lock().await.check_flush().await?;
+      }
+      // deletes keep their info as a tombstone so an older create or modify
+      // that shows up later is skipped too
+      let key = Key::from(&info_key(ex_id)?);
+      let mut lstore = self.lstore.lock().await;
+      if self.element_info && !info.is_empty() {
+        lstore.put(key, &encode_info(&info)?)?;
+      } else {
+        lstore.del(key)?;
+      }
+    }
+    Ok(())
+  }
+
+  // apply every diff in a local replication directory newer than the stored
+  // sequence (or start, if provided) and record the sequence after each one.
+  // returns the last applied sequence
+  pub async fn replicate(&mut self, dir: &std::path::Path, start: Option<u64>) -> Result<u64,Error> {
+    let state = replicate::State::from_path(&dir.join("state.txt"))?;
+    let mut sequence = match start {
+      Some(seq) => seq,
+      None => match self.get_meta("replication_sequence").await? {
+        Some(buf) => varint::decode(&buf)?.1,
+        None => E::MissingReplicationSequence.raise()?,
+      },
+    };
+    while sequence < state.sequence {
+      sequence += 1;
+      let file = replicate::sequence_path(dir, sequence, "osc.gz");
+      self.changeset_osc(std::io::BufReader::new(std::fs::File::open(&file)?)).await?;
+      let state_file = replicate::sequence_path(dir, sequence, "state.txt");
+      let timestamp = if state_file.exists() {
+        replicate::State::from_path(&state_file)?.timestamp
+      } else {
+        None
+      };
+      self.put_meta("replication_sequence", &meta::encode_u64(sequence)?).await?;
+      if let Some(t) = timestamp {
+        self.put_meta("replication_timestamp", t.as_bytes()).await?;
+      }
+      self.lstore.lock().await.flush()?;
+      if let Some(f) = self.reporter.lock().await.as_mut() {
+        f(Phase::Replicate(), Ok(()));
       }
     }
+    Ok(sequence)
+  }
+
+  // store the schema version for new databases or fail if it doesn't match,
+  // then read and write records in the layout of the database's version
+  pub async fn check_schema(&mut self) -> Result<(),Error> {
+    let version = migrate::check(&mut *self.lstore.lock().await, self.schema_version)?;
+    self.schema_version = Some(version);
+    Ok(())
+  }
+
+  pub fn get_schema_version(&self) -> u64 {
+    self.schema_version.unwrap_or(meta::SCHEMA_VERSION)
+  }
+
+  pub fn coords(&self) -> Coords {
+    Coords::from_schema_version(self.get_schema_version())
+  }
+
+  pub fn encode_options(&self) -> EncodeOptions<'_> {
+    EncodeOptions {
+      coords: self.coords(),
+      raw_tags: self.raw_tags,
+      element_info: self.element_info,
+      filter: self.filter.as_ref(),
+      mapping: self.mapping.as_ref(),
+      policy: self.label_policy.as_ref(),
+    }
+  }
+
+  pub async fn get_meta(&self, name: &str) -> Result<Option<Vec<u8>>,Error> {
+    self.lstore.lock().await.get(&Key::from(&meta_key(name)))
+  }
+
+  pub async fn put_meta(&self, name: &str, value: &[u8]) -> Result<(),Error> {
+    self.lstore.lock().await.put(Key::from(&meta_key(name)), value)
+  }
+
+  async fn flush_changeset(&mut self) -> Result<(),Error> {
     let mut lstore = self.lstore.lock().await;
+    write_header(&mut lstore, &Phase::Changeset(), self.get_schema_version())?;
     lstore.flush()?;
+    if let Some(n) = self.read_nodes().await { n.flush()? }
     let mut estore = self.estore.lock().await;
     estore.flush().await?;
     estore.sync().await?;
     Ok(())
   }
 
-  // call this when one of a record's dependants changes
+  // write back a record that retag_record changed
+  async fn write_record(&self, record: Option<(Vec<u8>,Vec<u8>)>) -> Result<(),Error> {
+    if let Some((key,value)) = record {
+      self.lstore.lock().await.put(Key::from(&key), &value)?;
+    }
+    Ok(())
+  }
+
+  // write a row produced by record_row into eyros and its backrefs into leveldb
+  async fn write_row(&self, row: Option<(P,Vec<u8>)>, backrefs: Vec<(u64,u64)>) -> Result<(),Error> {
+    if let Some((point,encoded)) = row {
+      let mut estore = self.estore.lock().await;
+      estore.create(point, encoded.into()).await?;
+    }
+    let mut lstore = self.lstore.lock().await;
+    for (a,b) in backrefs {
+      lstore.put(Key::from(&backref_key(a,b)?), &vec![])?;
+    }
+    Ok(())
+  }
+
+  // drop every backref and regenerate them from the records in the level db
+  // the same way process does. the eyros db is not touched
+  pub async fn rebuild_backrefs(&mut self) -> Result<(),Error> {
+    let nodes = self.read_nodes().await;
+    let mut lstore = self.lstore.lock().await;
+    let mut reporter = self.reporter.lock().await;
+    rebuild_backrefs(&mut lstore, nodes.as_deref(), self.coords(), || {
+      if let Some(f) = reporter.as_mut() { f(Phase::Backrefs(), Ok(())) }
+    })
+  }
+
+  // call this when one of a record's dependants changes. seen holds the records
+  // already recalculated, since relations can be members of each other
   #[async_recursion::async_recursion]
-  async fn recalculate(&self, ex_id: u64, prev_point: &Option<P>) -> Result<(),Error> {
+  async fn recalculate(&self, ex_id: u64, prev_point: &Option<P>, seen: &mut HashSet<u64>) -> Result<(),Error> {
+    if !seen.insert(ex_id) { return Ok(()) }
     let key = Key::from(&id_key(ex_id)?);
     let res = self.lstore.lock().await.get(&key)?;
     if let Some(buf) = res {
-      match decode(&key.data,&buf)? {
+      match decode(&key.data,&buf,self.coords())? {
         Decoded::Node(node) => {
           if let Some((new_point,encoded)) = self.encode_node(&node)? {
             let mut estore = self.estore.lock().await;
@@ -261,6 +804,13 @@ impl Ingest {
             let mut estore = self.estore.lock().await;
             estore.push_delete(p.clone(), ex_id);
           }
+          // relations with this node as a member
+          let backrefs = self.get_backrefs(ex_id).await?.into_iter()
+            .filter(|r| r%3 == 2).collect::<Vec<u64>>();
+          for r in backrefs.iter() {
+            let p = self.get_point(*r).await?;
+            self.recalculate(*r, &p, seen).await?;
+          }
         },
         Decoded::Way(way) => {
           if let Some((new_point,_deps,encoded)) = self.encode_way(&way).await? {
@@ -282,32 +832,38 @@ impl Ingest {
               estore.push_create(new_point.clone(), encoded.into());
             }
             for (r,p) in backrefs.iter().zip(prev_points.iter()) {
-              self.recalculate(*r, p).await?;
+              self.recalculate(*r, p, seen).await?;
             }
           }
         },
         Decoded::Relation(relation) => {
-          if let Some((new_point,_deps,encoded)) = self.encode_relation(&relation).await? {
-            let backrefs = self.get_backrefs(ex_id).await?;
-            let mut prev_points = Vec::with_capacity(backrefs.len());
-            for r in backrefs.iter() {
-              prev_points.push(self.get_point(*r).await?);
-            }
-            if relation.feature_type == self.place_other {
-              let mut estore = self.estore.lock().await;
-              if let Some(p) = prev_point {
-                estore.push_delete(p.clone(), relation.id*3+2);
-              }
-            } else if let Some(p) = prev_point {
+          let (row,_deps,problem) = self.encode_relation(&relation).await?;
+          if let (Some(e),Some(f)) = (problem,self.reporter.lock().await.as_mut()) {
+            f(Phase::Changeset(), Err(e));
+          }
+          let backrefs = self.get_backrefs(ex_id).await?;
+          let mut prev_points = Vec::with_capacity(backrefs.len());
+          for r in backrefs.iter() {
+            prev_points.push(self.get_point(*r).await?);
+          }
+          match (row,prev_point) {
+            (Some((new_point,encoded)),Some(p)) if relation.feature_type != self.place_other => {
               let mut estore = self.estore.lock().await;
               estore.push_update(p, &new_point, &encoded.into());
-            } else {
+            },
+            (Some((new_point,encoded)),None) if relation.feature_type != self.place_other => {
               let mut estore = self.estore.lock().await;
-              estore.push_create(new_point.clone(), encoded.into());
-            }
-            for (r,p) in backrefs.iter().zip(prev_points.iter()) {
-              self.recalculate(*r, p).await?;
-            }
+              estore.push_create(new_point, encoded.into());
+            },
+            // no longer rendered, like a multipolygon whose rings broke
+            (_,Some(p)) => {
+              let mut estore = self.estore.lock().await;
+              estore.push_delete(p.clone(), relation.id*3+2);
+            },
+            (_,None) => {},
+          }
+          for (r,p) in backrefs.iter().zip(prev_points.iter()) {
+            self.recalculate(*r, p, seen).await?;
           }
         },
       }
@@ -315,38 +871,20 @@ impl Ingest {
     Ok(())
   }
 
+  // the point of a record's row, computed from its nodes the same way as the row
   #[async_recursion::async_recursion]
   async fn get_point(&self, ex_id: u64) -> Result<Option<P>,Error> {
-    fn merge(a: &P, b: &P) -> P {
-      (merge_pt(&a.0,&b.0),merge_pt(&a.1,&b.1))
-    }
-    fn merge_pt(a: &eyros::Coord<f32>, b: &eyros::Coord<f32>) -> eyros::Coord<f32> {
-      let amin = match a {
-        eyros::Coord::Scalar(x) => x,
-        eyros::Coord::Interval(x,_) => x,
-      };
-      let amax = match a {
-        eyros::Coord::Scalar(x) => x,
-        eyros::Coord::Interval(_,x) => x,
-      };
-      let bmin = match b {
-        eyros::Coord::Scalar(x) => x,
-        eyros::Coord::Interval(x,_) => x,
-      };
-      let bmax = match b {
-        eyros::Coord::Scalar(x) => x,
-        eyros::Coord::Interval(_,x) => x,
-      };
-      eyros::Coord::Interval(amin.min(*bmin), amax.max(*bmax))
-    }
     Ok(match ex_id%3 {
       0 => {
+        if let Some((lon,lat)) = self.read_nodes().await.and_then(|n| n.get(ex_id/3,self.coords())) {
+          return Ok(Some((eyros::Coord::Scalar(lon),eyros::Coord::Scalar(lat))));
+        }
         let mut lstore = self.lstore.lock().await;
         let kbuf = id_key(ex_id)?;
         if let Some(buf) = lstore.get(&Key::from(&kbuf))? {
-          match decode(&kbuf, &buf)? {
+          match decode(&kbuf, &buf, self.coords())? {
             Decoded::Node(node) => {
-              Some((eyros::Coord::Scalar(node.lon),eyros::Coord::Scalar(node.lat)))
+              Some((eyros::Coord::Scalar(node.lon as f32),eyros::Coord::Scalar(node.lat as f32)))
             },
             _ => None
           }
@@ -355,185 +893,86 @@ impl Ingest {
         }
       },
       n => {
-        let refs = self.get_refs(ex_id).await?;
-        let mut bbox = None;
-        for r in refs.iter() {
-          let exr = r*3 + match n { 2 => 1, 1 => 0, _ => 0 };
-          if let Some(p) = self.get_point(exr).await? {
-            if let Some(b) = bbox {
-              bbox = Some(merge(&b, &p));
-            } else {
-              bbox = Some(p);
+        let ways = match n {
+          1 => vec![ex_id/3],
+          _ => self.get_relation_ways(ex_id).await?,
+        };
+        let mut lines = vec![];
+        for w in ways.iter() {
+          let mut ps = vec![];
+          for r in self.get_refs(w*3+1).await? {
+            if let Some((eyros::Coord::Scalar(lon),eyros::Coord::Scalar(lat))) = self.get_point(r*3+0).await? {
+              ps.push((lon,lat));
             }
           }
+          lines.push(ps);
         }
-        bbox
+        bbox_point(&lines)
       },
     })
   }
 
   fn encode_node(&self, node: &DecodedNode) -> Result<Option<(P,Vec<u8>)>,Error> {
-    if node.feature_type == self.place_other { return Ok(None) }
-    let encoded = georender_pack::encode::node_from_parsed(
-      node.id*3+0, (node.lon,node.lat), node.feature_type, &node.labels
-    )?;
-    if encoded.is_empty() { return Ok(None) }
-    let point = (eyros::Coord::Scalar(node.lon),eyros::Coord::Scalar(node.lat));
-    Ok(Some((point,encoded)))
+    node_row(node, self.place_other, self.region.as_ref())
   }
 
-  async fn create_node(&self, node: &DecodedNode) -> Result<(),Error> {
-    if let Some((point,encoded)) = self.encode_node(node)? {
-      let mut estore = self.estore.lock().await;
-      estore.create(point, encoded.into()).await?;
-    }
-    Ok(())
+  async fn encode_way(&self, way: &DecodedWay) -> Result<Option<(P,NodeDeps,Vec<u8>)>,Error> {
+    let nodes = self.read_nodes().await;
+    let mut lstore = self.lstore.lock().await;
+    way_row(&mut |key: &Key| lstore.get(key), &|id: u64| nodes.as_ref()?.get(id,self.coords()), self.coords(), way)
   }
 
-  async fn encode_way(&self, way: &DecodedWay) -> Result<Option<(P,NodeDeps,Vec<u8>)>,Error> {
-    let mut deps = HashMap::with_capacity(way.refs.len());
-    self.get_way_deps(way.refs.iter(), &mut deps).await?;
-    let mut bbox = (f32::INFINITY,f32::INFINITY,f32::NEG_INFINITY,f32::NEG_INFINITY);
-    for (lon,lat) in deps.values() {
-      bbox.0 = bbox.0.min(*lon);
-      bbox.1 = bbox.1.min(*lat);
-      bbox.2 = bbox.2.max(*lon);
-      bbox.3 = bbox.3.max(*lat);
-    }
-    let encoded = georender_pack::encode::way_from_parsed(
-      way.id*3+1, way.feature_type, way.is_area, &way.labels, &way.refs, &deps
-    )?;
-    if encoded.is_empty() {
-      Ok(None)
-    } else {
-      let point = (
-        eyros::Coord::Interval(bbox.0,bbox.2),
-        eyros::Coord::Interval(bbox.1,bbox.3),
-      );
-      Ok(Some((point,deps,encoded)))
-    }
+  async fn encode_relation(&self, relation: &DecodedRelation) -> Result<(Option<(P,Vec<u8>)>,WayDeps,Option<Error>),Error> {
+    let nodes = self.read_nodes().await;
+    let mut lstore = self.lstore.lock().await;
+    relation_row(&mut |key: &Key| lstore.get(key), &|id: u64| nodes.as_ref()?.get(id,self.coords()), self.coords(), relation)
   }
 
-  async fn create_way(&self, way: &DecodedWay) -> Result<(),Error> {
-    if let Some((point,deps,encoded)) = self.encode_way(way).await? {
-      if way.feature_type != self.place_other {
-        let mut estore = self.estore.lock().await;
-        estore.create(point, encoded.into()).await?;
-      }
-      let mut lstore = self.lstore.lock().await;
-      for r in deps.keys() {
-        // node -> way backref
-        lstore.put(Key::from(&backref_key(*r*3+0, way.id*3+1)?), &vec![])?;
-      }
-    }
-    Ok(())
+  async fn get_way_deps(&self, iter: impl Iterator<Item=&u64>, deps: &mut NodeDeps) -> Result<(),Error> {
+    let nodes = self.read_nodes().await;
+    let mut lstore = self.lstore.lock().await;
+    way_deps(&mut |key: &Key| lstore.get(key), &|id: u64| nodes.as_ref()?.get(id,self.coords()), self.coords(), iter, deps)
   }
 
-  async fn create_relation(&self, relation: &DecodedRelation) -> Result<(),Error> {
-    if let Some((point,deps,encoded)) = self.encode_relation(relation).await? {
-      if relation.feature_type != self.place_other {
-        let mut estore = self.estore.lock().await;
-        estore.create(point, encoded.into()).await?;
-      }
-      let mut lstore = self.lstore.lock().await;
-      for way_id in deps.keys() {
-        // way -> relation backref
-        lstore.put(Key::from(&backref_key(way_id*3+1,relation.id*3+2)?), &vec![])?;
-      }
-    }
-    Ok(())
+  async fn get_relation_deps(&self, iter: impl Iterator<Item=&u64>,
+    node_deps: &mut NodeDeps, way_deps: &mut WayDeps
+  ) -> Result<(),Error> {
+    let nodes = self.read_nodes().await;
+    let mut lstore = self.lstore.lock().await;
+    relation_deps(&mut |key: &Key| lstore.get(key), &|id: u64| nodes.as_ref()?.get(id,self.coords()), self.coords(), iter, node_deps, way_deps)
   }
 
-  async fn encode_relation(&self, relation: &DecodedRelation) -> Result<Option<(P,WayDeps,Vec<u8>)>,Error> {
-    let mut node_deps = HashMap::new();
-    let mut way_deps = HashMap::with_capacity(relation.members.len());
-    let refs = relation.members.iter().map(|m| m/2).collect::<Vec<u64>>();
-    self.get_relation_deps(refs.iter(), &mut node_deps, &mut way_deps).await?;
-    let mut bbox = (f32::INFINITY,f32::INFINITY,f32::NEG_INFINITY,f32::NEG_INFINITY);
-    for refs in way_deps.values() {
-      for r in refs.iter() {
-        if let Some(p) = node_deps.get(r) {
-          bbox.0 = bbox.0.min(p.0);
-          bbox.1 = bbox.1.min(p.1);
-          bbox.2 = bbox.2.max(p.0);
-          bbox.3 = bbox.3.max(p.1);
-        }
-      }
-    }
-    let members = relation.members.iter().map(|m| {
-      georender_pack::Member::new(
-        m/2,
-        match m%2 {
-          0 => georender_pack::MemberRole::Outer(),
-          _ => georender_pack::MemberRole::Inner(),
+  // the ways that make up a relation's geometry, including those of nested relations
+  // and the ways of routes
+  async fn get_relation_ways(&self, ex_id: u64) -> Result<Vec<u64>,Error> {
+    let mut lstore = self.lstore.lock().await;
+    let key = id_key(ex_id)?;
+    Ok(match lstore.get(&Key::from(&key))? {
+      Some(buf) => match decode(&key, &buf, self.coords())? {
+        Decoded::Relation(relation) => {
+          let get: &mut Get = &mut |key: &Key| lstore.get(key);
+          let mut ways = relation_ways(get, self.coords(), &relation)?
+            .iter().map(|m| m/2).collect::<Vec<u64>>();
+          ways.extend(route_ways(get, ex_id)?);
+          ways
         },
-        georender_pack::MemberType::Way()
-      )
-    }).collect::<Vec<_>>();
-    let encoded = georender_pack::encode::relation_from_parsed(
-      relation.id*3+2, relation.feature_type, relation.is_area,
-      &relation.labels, &members, &node_deps, &way_deps
-    )?;
-    if encoded.is_empty() {
-      Ok(None)
-    } else {
-      let point = (
-        eyros::Coord::Interval(bbox.0,bbox.2),
-        eyros::Coord::Interval(bbox.1,bbox.3),
-      );
-      Ok(Some((point,way_deps,encoded)))
-    }
+        _ => vec![],
+      },
+      None => vec![],
+    })
   }
 
-  async fn get_way_deps(&self, iter: impl Iterator<Item=&u64>, deps: &mut NodeDeps) -> Result<(),Error> {
+  // extended ids of the node and relation members of a relation
+  pub(crate) async fn get_member_refs(&self, ex_id: u64) -> Result<Vec<u64>,Error> {
     let mut lstore = self.lstore.lock().await;
-    let mut key_data = [ID_PREFIX,0,0,0,0,0,0,0,0];
-    key_data[0] = ID_PREFIX;
-    let mut first = None;
-    for r in iter {
-      if first.is_none() {
-        first = Some(*r);
-      } else if first == Some(*r) {
-        continue;
-      }
-      let s = varint::encode(r*3+0,&mut key_data[1..])?;
-      let key = Key::from(&key_data[0..1+s]);
-      if let Some(buf) = lstore.get(&key)? {
-        match decode(&key.data,&buf)? {
-          Decoded::Node(node) => {
-            deps.insert(*r,(node.lon,node.lat));
-          },
-          _ => {},
-        }
-      }
-    }
-    Ok(())
+    member_refs(&mut |key: &Key| lstore.get(key), ex_id)
   }
 
-  async fn get_relation_deps(&self, iter: impl Iterator<Item=&u64>,
-    node_deps: &mut NodeDeps, way_deps: &mut WayDeps
-  ) -> Result<(),Error> {
-    let mut key_data = [ID_PREFIX,0,0,0,0,0,0,0,0];
-    key_data[0] = ID_PREFIX;
-    for m in iter {
-      key_data[1..].fill(0);
-      let s = varint::encode(m*3+1,&mut key_data[1..])?;
-      let key = Key::from(&key_data[0..1+s]);
-      let res = {
-        let mut lstore = self.lstore.lock().await;
-        lstore.get(&key)?
-      };
-      if let Some(buf) = res {
-        match decode(&key.data,&buf)? {
-          Decoded::Way(way) => {
-            way_deps.insert(way.id, way.refs.clone());
-            self.get_way_deps(way.refs.iter(), node_deps).await?;
-          },
-          _ => {},
-        }
-      }
+  async fn read_nodes(&self) -> Option<async_std::sync::RwLockReadGuard<'_,NodeCache>> {
+    match &self.nodes {
+      Some(n) => Some(n.read().await),
+      None => None,
     }
-    Ok(())
   }
 
   async fn get_backrefs(&self, ex_id: u64) -> Result<Vec<u64>,Error> {
@@ -605,6 +1044,449 @@ impl Ingest {
   }
 }
 
+// encode every element in a pbf blob, sorted by key so that
+// writes land in leveldb in order
+pub fn encode_blob(blob: osmpbf::Blob, opts: &EncodeOptions)
+-> Result<Vec<Result<(Vec<u8>,Vec<u8>),Error>>,Error> {
+  let mut records = vec![];
+  if let osmpbf::BlobDecode::OsmData(block) = blob.decode()? {
+    block.for_each_element(|element| match Element::from_osmpbf(&element) {
+      Ok(element) => {
+        records.push(encode_element(&element, opts));
+        if opts.raw_tags { records.push(encode_element_tags(&element)) }
+        if opts.element_info {
+          if let Some(record) = encode_element_info(&element).transpose() { records.push(record) }
+        }
+        if let Some(record) = encode_element_members(&element).transpose() { records.push(record) }
+      },
+      Err(e) => records.push(Err(e)),
+    });
+  }
+  records.sort_by(|a,b| match (a,b) {
+    (Ok((ka,_)),Ok((kb,_))) => ka.cmp(kb),
+    (Err(_),Ok(_)) => std::cmp::Ordering::Less,
+    (Ok(_),Err(_)) => std::cmp::Ordering::Greater,
+    (Err(_),Err(_)) => std::cmp::Ordering::Equal,
+  });
+  Ok(records)
+}
+
+pub fn meta_key(name: &str) -> Vec<u8> {
+  let mut key = vec![META_PREFIX];
+  key.extend_from_slice(name.as_bytes());
+  key
+}
+
+// record which tool and schema last wrote to the db and when each phase ran
+fn write_header(lstore: &mut LStore, phase: &Phase, schema_version: u64) -> Result<(),Error> {
+  const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+  let version = VERSION.unwrap_or("unknown");
+  lstore.put(Key::from(&meta_key("version")), version.as_bytes())?;
+  lstore.put(Key::from(&meta_key("schema_version")), &meta::encode_u64(schema_version)?)?;
+  lstore.put(Key::from(&meta_key(&format!["{}_time", phase.to_string()])), meta::now().as_bytes())?;
+  match phase {
+    Phase::Pbf() | Phase::Xml() | Phase::O5m() => {
+      lstore.put(Key::from(&meta_key("format")), phase.to_string().as_bytes())?;
+    },
+    _ => {},
+  }
+  Ok(())
+}
+
+fn write_pbf_header(lstore: &mut LStore, header: &meta::PbfHeader) -> Result<(),Error> {
+  if let Some(bbox) = &header.bbox {
+    lstore.put(Key::from(&meta_key("bbox")), &meta::encode_bbox(bbox)?)?;
+  }
+  if let Some(program) = &header.writing_program {
+    lstore.put(Key::from(&meta_key("writing_program")), program.as_bytes())?;
+  }
+  if let Some(source) = &header.source {
+    lstore.put(Key::from(&meta_key("source")), source.as_bytes())?;
+  }
+  if let Some(t) = header.replication_timestamp {
+    lstore.put(Key::from(&meta_key("replication_timestamp")), meta::iso_time(t).as_bytes())?;
+  }
+  if let Some(seq) = header.replication_sequence {
+    lstore.put(Key::from(&meta_key("replication_sequence")), &meta::encode_u64(seq as u64)?)?;
+  }
+  if let Some(url) = &header.replication_base_url {
+    lstore.put(Key::from(&meta_key("replication_base_url")), url.as_bytes())?;
+  }
+  Ok(())
+}
+
+// record lookups for encoding. the LStore getter sees pending writes
+// while the process workers read from a leveldb snapshot
+type Get<'a> = dyn FnMut(&Key) -> Result<Option<Vec<u8>>,Error>+'a;
+
+// node locations from the node cache, if there is one
+type GetNode<'a> = dyn Fn(u64) -> Option<(f32,f32)>+'a;
+
+// a leveldb snapshot read by every process worker. leveldb allows reads from
+// one snapshot on many threads, but the wrapper holds raw pointers
+struct SharedSnapshot<'a>(Snapshot<'a,Key>);
+unsafe impl Sync for SharedSnapshot<'_> {}
+
+// the eyros row (if any) and the (referenced,referencing) backref pairs
+// that the process phase writes for a record, along with a problem with the
+// record to report, such as a broken multipolygon
+fn record_row(get: &mut Get, nodes: &GetNode, coords: Coords, place_other: u64,
+  region: Option<&extract::Region>, decoded: &Decoded
+) -> Result<(Option<(P,Vec<u8>)>,Vec<(u64,u64)>,Option<Error>),Error> {
+  Ok(match decoded {
+    Decoded::Node(node) => (node_row(node, place_other, region)?, vec![], None),
+    Decoded::Way(way) => match way_row(get, nodes, coords, way)? {
+      Some((point,deps,encoded)) => (
+        if way.feature_type == place_other { None } else { Some((point,encoded)) },
+        // node -> way backrefs
+        deps.keys().map(|r| (r*3+0, way.id*3+1)).collect(),
+        None,
+      ),
+      None => (None, vec![], None),
+    },
+    Decoded::Relation(relation) => {
+      // node, relation, and route way member -> relation backrefs.
+      // routes can pass along the same way more than once
+      let mut backrefs = vec![];
+      for r in member_refs(get, relation.id*3+2)?.into_iter().collect::<HashSet<u64>>() {
+        if get(&Key::from(&id_key(r)?))?.is_some() {
+          backrefs.push((r, relation.id*3+2));
+        }
+      }
+      let (row,deps,problem) = relation_row(get, nodes, coords, relation)?;
+      // way -> relation backrefs, for direct members only. these are kept
+      // without a row too, so fixing a broken multipolygon updates it
+      backrefs.extend(relation.members.iter().map(|m| m/2)
+        .filter(|r| deps.contains_key(r))
+        .collect::<HashSet<u64>>().into_iter()
+        .map(|r| (r*3+1, relation.id*3+2)));
+      (
+        row.filter(|_| relation.feature_type != place_other),
+        backrefs,
+        problem,
+      )
+    },
+  })
+}
+
+fn node_row(node: &DecodedNode, place_other: u64, region: Option<&extract::Region>)
+-> Result<Option<(P,Vec<u8>)>,Error> {
+  if node.feature_type == place_other { return Ok(None) }
+  // nodes outside of an extract are only there to complete ways
+  if region.map(|r| !r.contains(node.lon, node.lat)).unwrap_or(false) { return Ok(None) }
+  let encoded = georender_pack::encode::node_from_parsed(
+    node.id*3+0, (node.lon as f32,node.lat as f32), node.feature_type, &node.labels
+  )?;
+  if encoded.is_empty() { return Ok(None) }
+  let point = (eyros::Coord::Scalar(node.lon as f32),eyros::Coord::Scalar(node.lat as f32));
+  Ok(Some((point,encoded)))
+}
+
+fn way_row(get: &mut Get, nodes: &GetNode, coords: Coords, way: &DecodedWay) -> Result<Option<(P,NodeDeps,Vec<u8>)>,Error> {
+  let mut deps = HashMap::with_capacity(way.refs.len());
+  way_deps(get, nodes, coords, way.refs.iter(), &mut deps)?;
+  let encoded = georender_pack::encode::way_from_parsed(
+    way.id*3+1, way.feature_type, way.is_area, &way.labels, &way.refs, &deps
+  )?;
+  match bbox_point(&[way.refs.iter().filter_map(|r| deps.get(r).copied()).collect()]) {
+    Some(point) if !encoded.is_empty() => Ok(Some((point,deps,encoded))),
+    _ => Ok(None),
+  }
+}
+
+// the row of a relation, the ways it was built from, and the reason it has no
+// row if its ways don't make a valid multipolygon
+fn relation_row(get: &mut Get, nodes: &GetNode, coords: Coords, relation: &DecodedRelation)
+-> Result<(Option<(P,Vec<u8>)>,WayDeps,Option<Error>),Error> {
+  let route = route_ways(get, relation.id*3+2)?;
+  if !route.is_empty() {
+    let (row,deps) = route_row(get, nodes, coords, relation, &route)?;
+    return Ok((row,deps,None));
+  }
+  let ways = relation_ways(get, coords, relation)?;
+  let mut node_deps = HashMap::new();
+  let mut way_deps = HashMap::with_capacity(ways.len());
+  let refs = ways.iter().map(|m| m/2).collect::<Vec<u64>>();
+  relation_deps(get, nodes, coords, refs.iter(), &mut node_deps, &mut way_deps)?;
+  let point = bbox_point(&way_deps.values().map(|refs| {
+    refs.iter().filter_map(|r| node_deps.get(r).copied()).collect()
+  }).collect::<Vec<_>>());
+  if !relation.is_area { return Ok((None,way_deps,None)) }
+  // georender gets the assembled rings as closed ways in place of the members
+  let rings = match multipolygon::assemble(relation.id, &ways, &way_deps, &node_deps) {
+    Ok(rings) => rings,
+    Err(e) => return Ok((None,way_deps,Some(e))),
+  };
+  let ring_ways = rings.iter().enumerate()
+    .map(|(i,ring)| (i as u64, ring.refs.clone()))
+    .collect::<WayDeps>();
+  let members = rings.iter().enumerate().map(|(i,ring)| {
+    georender_pack::Member::new(
+      i as u64,
+      match ring.inner {
+        false => georender_pack::MemberRole::Outer(),
+        true => georender_pack::MemberRole::Inner(),
+      },
+      georender_pack::MemberType::Way()
+    )
+  }).collect::<Vec<_>>();
+  let encoded = georender_pack::encode::relation_from_parsed(
+    relation.id*3+2, relation.feature_type, relation.is_area,
+    &relation.labels, &members, &node_deps, &ring_ways
+  )?;
+  match point {
+    Some(point) if !encoded.is_empty() => Ok((Some((point,encoded)),way_deps,None)),
+    _ => Ok((None,way_deps,None)),
+  }
+}
+
+// routes are one line through their member ways in order. each way is
+// reversed when that lines it up with the end of the line so far, and the
+// first way is reversed when only its start meets the second. ways that don't
+// meet the line are joined to it with a straight segment
+fn route_row(get: &mut Get, nodes: &GetNode, coords: Coords, relation: &DecodedRelation,
+  route: &[u64]
+) -> Result<(Option<(P,Vec<u8>)>,WayDeps),Error> {
+  let mut node_deps = HashMap::new();
+  let mut way_deps = HashMap::with_capacity(route.len());
+  relation_deps(get, nodes, coords, route.iter(), &mut node_deps, &mut way_deps)?;
+  let mut refs: Vec<u64> = vec![];
+  let mut first = true;
+  for w in route.iter() {
+    let wrefs = match way_deps.get(w) {
+      Some(wrefs) if !wrefs.is_empty() => wrefs,
+      _ => continue,
+    };
+    let (start,end) = (wrefs[0],wrefs[wrefs.len()-1]);
+    if refs.is_empty() {
+      refs.extend(wrefs.iter());
+      continue;
+    }
+    if first && refs.last() != Some(&start) && refs.last() != Some(&end)
+    && (refs[0] == start || refs[0] == end) {
+      refs.reverse();
+    }
+    first = false;
+    if refs.last() == Some(&start) {
+      refs.extend(wrefs[1..].iter());
+    } else if refs.last() == Some(&end) {
+      refs.extend(wrefs.iter().rev().skip(1));
+    } else {
+      refs.extend(wrefs.iter());
+    }
+  }
+  refs.retain(|r| node_deps.contains_key(r));
+  let encoded = georender_pack::encode::way_from_parsed(
+    relation.id*3+2, relation.feature_type, false, &relation.labels, &refs, &node_deps
+  )?;
+  match bbox_point(&[refs.iter().map(|r| *node_deps.get(r).unwrap()).collect()]) {
+    Some(point) if !encoded.is_empty() => Ok((Some((point,encoded)),way_deps)),
+    _ => Ok((None,way_deps)),
+  }
+}
+
+// the eyros point of a row from the node locations of its lines in order.
+// a segment between nodes more than 180 degrees of longitude apart crosses
+// the antimeridian, so the longitude interval runs west to east across it
+// with west > east. EStore stores those as a row on each side (see split_point)
+fn bbox_point(lines: &[Vec<(f32,f32)>]) -> Option<P> {
+  let mut bbox = (f32::INFINITY,f32::INFINITY,f32::NEG_INFINITY,f32::NEG_INFINITY);
+  // longitudes west of 0 shifted by 360, for the interval across the antimeridian
+  let mut wrapped = (f32::INFINITY,f32::NEG_INFINITY);
+  let mut crosses = false;
+  for ps in lines.iter() {
+    for (i,(lon,lat)) in ps.iter().enumerate() {
+      bbox.0 = bbox.0.min(*lon);
+      bbox.1 = bbox.1.min(*lat);
+      bbox.2 = bbox.2.max(*lon);
+      bbox.3 = bbox.3.max(*lat);
+      let w = if *lon < 0.0 { lon + 360.0 } else { *lon };
+      wrapped = (wrapped.0.min(w), wrapped.1.max(w));
+      if i > 0 && (lon - ps[i-1].0).abs() > 180.0 { crosses = true }
+    }
+  }
+  if bbox.0 > bbox.2 { return None }
+  let lon = match crosses && wrapped.1 - wrapped.0 < 360.0 {
+    true => eyros::Coord::Interval(wrapped.0,wrapped.1-360.0),
+    false => eyros::Coord::Interval(bbox.0,bbox.2),
+  };
+  Some((lon,eyros::Coord::Interval(bbox.1,bbox.3)))
+}
+
+fn way_deps<'a>(get: &mut Get, nodes: &GetNode, coords: Coords, iter: impl Iterator<Item=&'a u64>,
+  deps: &mut NodeDeps
+) -> Result<(),Error> {
+  let mut key_data = [ID_PREFIX,0,0,0,0,0,0,0,0];
+  let mut first = None;
+  for r in iter {
+    if first.is_none() {
+      first = Some(*r);
+    } else if first == Some(*r) {
+      continue;
+    }
+    if let Some(p) = nodes(*r) {
+      deps.insert(*r,p);
+      continue;
+    }
+    let s = varint::encode(r*3+0,&mut key_data[1..])?;
+    let key = Key::from(&key_data[0..1+s]);
+    if let Some(buf) = get(&key)? {
+      match decode(&key.data,&buf,coords)? {
+        Decoded::Node(node) => {
+          // georender takes f32 locations
+          deps.insert(*r,(node.lon as f32,node.lat as f32));
+        },
+        _ => {},
+      }
+    }
+  }
+  Ok(())
+}
+
+fn relation_deps<'a>(get: &mut Get, nodes: &GetNode, coords: Coords, iter: impl Iterator<Item=&'a u64>,
+  node_deps: &mut NodeDeps, way_deps: &mut WayDeps
+) -> Result<(),Error> {
+  let mut key_data = [ID_PREFIX,0,0,0,0,0,0,0,0];
+  for m in iter {
+    key_data[1..].fill(0);
+    let s = varint::encode(m*3+1,&mut key_data[1..])?;
+    let key = Key::from(&key_data[0..1+s]);
+    if let Some(buf) = get(&key)? {
+      match decode(&key.data,&buf,coords)? {
+        Decoded::Way(way) => {
+          way_deps.insert(way.id, way.refs.clone());
+          self::way_deps(get, nodes, coords, way.refs.iter(), node_deps)?;
+        },
+        _ => {},
+      }
+    }
+  }
+  Ok(())
+}
+
+// the way members of a relation (id*2 + inner) followed by the ways of member
+// relations with an inner or outer role, so nested multipolygons render as one.
+// every way of an inner member relation is inner, and each relation is only
+// visited once so that cycles between relations end
+fn relation_ways(get: &mut Get, coords: Coords, relation: &DecodedRelation) -> Result<Vec<u64>,Error> {
+  let mut ways = relation.members.clone();
+  let mut visited = HashSet::new();
+  visited.insert(relation.id*3+2);
+  let mut stack = vec![(relation.id*3+2,false)];
+  while let Some((ex_id,inner)) = stack.pop() {
+    for m in member_refs_with_roles(get, ex_id)? {
+      let (r,role) = (m/4,m%4);
+      if r%3 != 2 || role == ROLE_OTHER || !visited.insert(r) { continue }
+      let inner = inner || role == ROLE_INNER;
+      let key = id_key(r)?;
+      if let Some(buf) = get(&Key::from(&key))? {
+        if let Decoded::Relation(member) = decode(&key, &buf, coords)? {
+          ways.extend(member.members.iter().map(|w| if inner { w|1 } else { *w }));
+          stack.push((r,inner));
+        }
+      }
+    }
+  }
+  Ok(ways)
+}
+
+// node and relation members of a relation as ex_id*4 + role
+fn member_refs_with_roles(get: &mut Get, ex_id: u64) -> Result<Vec<u64>,Error> {
+  Ok(match get(&Key::from(&members_key(ex_id)?))? {
+    Some(buf) => decode_members(&buf)?,
+    None => vec![],
+  })
+}
+
+fn member_refs(get: &mut Get, ex_id: u64) -> Result<Vec<u64>,Error> {
+  Ok(member_refs_with_roles(get, ex_id)?.iter().map(|m| m/4).collect())
+}
+
+// way ids of a route relation in member order, empty for other relations
+fn route_ways(get: &mut Get, ex_id: u64) -> Result<Vec<u64>,Error> {
+  Ok(member_refs(get, ex_id)?.into_iter().filter(|r| r%3 == 1).map(|r| r/3).collect())
+}
+
+// when raw tags are stored for a record, derive its feature type, labels, and
+// area flag from them again so that a new tag mapping or filter takes effect.
+// also returns the updated key and value if the record changed
+fn retag_record(get: &mut Get, coords: Coords, filter: Option<&filter::Filter>,
+  mapping: Option<&mapping::Mapping>, policy: Option<&label::LabelPolicy>, decoded: Decoded
+) -> Result<(Decoded,Option<(Vec<u8>,Vec<u8>)>),Error> {
+  let ex_id = decoded.ex_id();
+  let mut tags = match get(&Key::from(&tags_key(ex_id)?))? {
+    Some(buf) => decode_tags(&buf)?,
+    None => return Ok((decoded,None)),
+  };
+  if let Some(f) = filter {
+    let tagv = tags.iter().map(|(k,v)| (k.as_str(),v.as_str())).collect::<Vec<_>>();
+    if !f.matches(ex_id%3, &tagv) { tags.clear() }
+  }
+  let retagged = retag(&decoded, &tags, mapping, policy)?;
+  if retagged == decoded { return Ok((decoded,None)) }
+  let value = encode_record(&retagged, coords)?;
+  Ok((retagged,Some((id_key(ex_id)?,value))))
+}
+
+// delete every backref and write them again from the refs and members of the
+// records alone, without encoding them. like in process, only records that
+// exist get a backref
+pub fn rebuild_backrefs(lstore: &mut LStore, nodes: Option<&NodeCache>, coords: Coords,
+  mut report: impl FnMut()
+) -> Result<(),Error> {
+  lstore.flush()?;
+  let db = lstore.db.clone();
+  {
+    let gt = Key::from(&[BACKREF_PREFIX]);
+    let lt = Key::from(&[BACKREF_PREFIX+1]);
+    let iter = db.keys_iter(ReadOptions::new());
+    iter.seek(&gt);
+    for key in iter.take_while(move |k| *k < lt) {
+      lstore.del(key)?;
+    }
+    lstore.flush()?;
+  }
+  let gt = Key::from(&[ID_PREFIX]);
+  let lt = Key::from(&[ID_PREFIX+1]);
+  let iter = db.iter(ReadOptions::new());
+  iter.seek(&gt);
+  for (key,value) in iter.take_while(move |(k,_)| *k < lt) {
+    let decoded = decode(&key.data,&value,coords)?;
+    let ex_id = decoded.ex_id();
+    let refs = match &decoded {
+      Decoded::Node(_) => HashSet::new(),
+      Decoded::Way(way) => way.refs.iter().map(|r| r*3+0).collect::<HashSet<u64>>(),
+      Decoded::Relation(relation) => {
+        let mut refs = relation.members.iter().map(|m| (m/2)*3+1).collect::<HashSet<u64>>();
+        refs.extend(member_refs(&mut |key: &Key| lstore.get(key), ex_id)?);
+        refs
+      },
+    };
+    for r in refs {
+      let cached = r%3 == 0 && nodes.and_then(|n| n.get(r/3,coords)).is_some();
+      if cached || lstore.get(&Key::from(&id_key(r)?))?.is_some() {
+        lstore.put(Key::from(&backref_key(r,ex_id)?), &vec![])?;
+      }
+    }
+    report();
+  }
+  lstore.flush()?;
+  Ok(())
+}
+
+// write an id or tags record and keep the node cache in sync with node records
+fn put_record(lstore: &mut LStore, nodes: Option<&mut NodeCache>, coords: Coords,
+  key: &[u8], value: &[u8]
+) -> Result<(),Error> {
+  lstore.put(Key::from(key), value)?;
+  if let (Some(n),ID_PREFIX) = (nodes,key[0]) {
+    if let Decoded::Node(node) = decode(key, value, coords)? {
+      n.set(node.id, node.lon, node.lat, coords)?;
+    }
+  }
+  Ok(())
+}
+
 fn backref_key(a: u64, b: u64) -> Result<Vec<u8>,Error> {
   // both a and b are extended ids
   let mut key = vec![0u8;1+varint::length(a)+varint::length(b)];
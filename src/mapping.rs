@@ -0,0 +1,133 @@
+use crate::{Error,error::IngestErrorKind as E,filter::Rule};
+use std::collections::HashMap;
+
+const DEFAULT_PRIORITY: u64 = 50;
+
+// feature types on top of georender's, one rule per line:
+//   [nwr/]key[=value[,value...]] type [priority] [area|line]
+// patterns are the same as in tag filters. type is a feature type id or the
+// name of a georender type like amenity.cafe, priority defaults to 50, and
+// area or line overrides whether ways and relations are areas.
+// a rule is used over the georender type of the same tags if its priority is
+// at least as high. a line of the form `* type` sets the type of elements that
+// nothing matched (place.other by default), which process does not render
+#[derive(Debug,Clone,PartialEq)]
+pub struct Mapping {
+  rules: Vec<(Rule,Target)>,
+  other: u64,
+  place_other: u64,
+  types: HashMap<&'static str,u64>,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+struct Target {
+  feature_type: u64,
+  priority: u64,
+  area: Option<bool>,
+}
+
+impl Mapping {
+  pub fn new() -> Self {
+    let types = georender_pack::osm_types::get_types();
+    let place_other = *types.get("place.other").unwrap();
+    Self { rules: vec![], other: place_other, place_other, types }
+  }
+
+  pub fn push(&mut self, line: &str) -> Result<(),Error> {
+    let fields = line.split_whitespace().collect::<Vec<_>>();
+    let invalid = || E::InvalidMapping { line: line.to_string() };
+    if fields.len() < 2 || fields.len() > 4 { invalid().raise()? }
+    let feature_type = match fields[1].parse::<u64>() {
+      Ok(ft) => ft,
+      Err(_) => match self.types.get(fields[1]) {
+        Some(ft) => *ft,
+        None => invalid().raise()?,
+      },
+    };
+    if fields[0] == "*" {
+      if fields.len() > 2 { invalid().raise()? }
+      self.other = feature_type;
+      return Ok(());
+    }
+    let rule = Rule::parse(fields[0])?;
+    if rule.exclude { invalid().raise()? }
+    let mut target = Target { feature_type, priority: DEFAULT_PRIORITY, area: None };
+    let mut rest = fields[2..].iter().peekable();
+    if let Some(p) = rest.peek().and_then(|x| x.parse::<u64>().ok()) {
+      target.priority = p;
+      rest.next();
+    }
+    target.area = match rest.next() {
+      Some(&"area") => Some(true),
+      Some(&"line") => Some(false),
+      Some(_) => invalid().raise()?,
+      None => None,
+    };
+    if rest.next().is_some() { invalid().raise()? }
+    self.rules.push((rule,target));
+    Ok(())
+  }
+
+  // one rule per line. blank lines and lines starting with # are skipped
+  pub fn parse(src: &str) -> Result<Self,Error> {
+    let mut mapping = Self::new();
+    for line in src.lines().map(|line| line.trim()) {
+      if line.is_empty() || line.starts_with('#') { continue }
+      mapping.push(line)?;
+    }
+    Ok(mapping)
+  }
+
+  pub fn from_path(path: &std::path::Path) -> Result<Self,Error> {
+    Self::parse(&std::fs::read_to_string(path)?)
+  }
+
+  // the feature type of elements that nothing matched
+  pub fn other(&self) -> u64 {
+    self.other
+  }
+
+  // the feature type and area override for an element given the type that
+  // georender picked for the same tags. t is the element type like in Filter
+  pub fn feature_type(&self, t: u64, tags: &[(&str,&str)], ft: u64) -> (u64,Option<bool>) {
+    let mut top: Option<&Target> = None;
+    for (rule,target) in self.rules.iter() {
+      if top.map(|x| target.priority > x.priority).unwrap_or(true) && rule.matches(t, tags) {
+        top = Some(target);
+      }
+    }
+    match top {
+      Some(target) if target.priority >= self.priority(tags) => (target.feature_type,target.area),
+      _ if ft == self.place_other => (self.other,None),
+      _ => (ft,None),
+    }
+  }
+
+  // priority of the type georender picks, 0 when it has none
+  fn priority(&self, tags: &[(&str,&str)]) -> u64 {
+    tags.iter()
+      .filter(|(k,v)| self.types.contains_key(format!["{}.{}",k,v].as_str()))
+      .filter_map(georender_pack::tags::get_tag_priority)
+      .max()
+      .unwrap_or(0)
+  }
+}
+
+impl Default for Mapping {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// the feature type, labels, and area override to encode an element with
+pub fn parse_tags(mapping: Option<&Mapping>, t: u64, tags: &[(&str,&str)])
+-> Result<(u64,Vec<u8>,Option<bool>),Error> {
+  let (ft,labels) = georender_pack::tags::parse(tags)?;
+  Ok(match mapping {
+    Some(m) => {
+      let (ft,area) = m.feature_type(t, tags, ft);
+      (ft,labels,area)
+    },
+    None => (ft,labels,None),
+  })
+}
@@ -0,0 +1,252 @@
+use std::io::Read;
+use desert::{ToBytesBE,FromBytesBE,varint};
+use crate::{Error,error::IngestErrorKind as E,LStore,Key,META_PREFIX};
+
+// schema version of new databases
+pub const SCHEMA_VERSION: u64 = 1;
+// newest schema version that can be read and written. bump this whenever the
+// layout of records in the level db changes. version 2 stores node locations
+// as fixed-point integers and is opt-in
+pub const LATEST_SCHEMA_VERSION: u64 = 2;
+
+// size limits of the pbf format, checked before allocating for lengths read
+// from a file that might not be a pbf at all
+const MAX_BLOB_HEADER_SIZE: usize = 64*1024;
+const MAX_BLOB_SIZE: usize = 32*1024*1024;
+
+// the fields of the pbf HeaderBlock that are worth keeping around.
+// osmpbf does not expose these, so the first blob is parsed here
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct PbfHeader {
+  pub bbox: Option<(f64,f64,f64,f64)>, // west, south, east, north
+  pub writing_program: Option<String>,
+  pub source: Option<String>,
+  pub replication_timestamp: Option<i64>,
+  pub replication_sequence: Option<i64>,
+  pub replication_base_url: Option<String>,
+}
+
+impl PbfHeader {
+  // read the leading OSMHeader blob. returns the parsed header (if there was
+  // one) along with every byte consumed so the caller can replay them
+  pub fn read<R: Read>(reader: &mut R) -> Result<(Option<Self>,Vec<u8>),Error> {
+    let mut consumed = vec![];
+    let mut len_buf = [0u8;4];
+    if reader.read_exact(&mut len_buf).is_err() { return Ok((None,consumed)) }
+    consumed.extend_from_slice(&len_buf);
+    let header_len = u32::from_be_bytes(len_buf) as usize;
+    if header_len > MAX_BLOB_HEADER_SIZE {
+      E::InvalidPbfHeader { message: format!["blob header of {} bytes is too large", header_len] }.raise()?
+    }
+    let mut header = vec![0u8;header_len];
+    reader.read_exact(&mut header)?;
+    consumed.extend_from_slice(&header);
+
+    let mut blob_type = None;
+    let mut data_size = 0;
+    for field in Fields::new(&header) {
+      match field? {
+        (1,Value::Bytes(b)) => blob_type = Some(b),
+        (3,Value::Varint(n)) => data_size = n as usize,
+        _ => {},
+      }
+    }
+    if blob_type != Some(&b"OSMHeader"[..]) { return Ok((None,consumed)) }
+    if data_size > MAX_BLOB_SIZE {
+      E::InvalidPbfHeader { message: format!["blob of {} bytes is too large", data_size] }.raise()?
+    }
+    let mut blob = vec![0u8;data_size];
+    reader.read_exact(&mut blob)?;
+    consumed.extend_from_slice(&blob);
+
+    let mut data = None;
+    for field in Fields::new(&blob) {
+      match field? {
+        (1,Value::Bytes(b)) => data = Some(b.to_vec()),
+        (3,Value::Bytes(b)) => {
+          let mut buf = vec![];
+          flate2::read::ZlibDecoder::new(b).read_to_end(&mut buf)?;
+          data = Some(buf);
+        },
+        _ => {},
+      }
+    }
+    Ok((data.map(|d| Self::parse(&d)).transpose()?,consumed))
+  }
+
+  fn parse(buf: &[u8]) -> Result<Self,Error> {
+    let mut h = Self::default();
+    for field in Fields::new(buf) {
+      match field? {
+        (1,Value::Bytes(b)) => {
+          // HeaderBBox stores sint64 nanodegrees as left, right, top, bottom
+          let mut bbox = [0f64;4];
+          for f in Fields::new(b) {
+            if let (i@1..=4,Value::Varint(n)) = f? {
+              let x = ((n >> 1) as i64) ^ -((n & 1) as i64);
+              bbox[i as usize - 1] = (x as f64) * 1e-9;
+            }
+          }
+          h.bbox = Some((bbox[0],bbox[3],bbox[1],bbox[2]));
+        },
+        (16,Value::Bytes(b)) => h.writing_program = Some(String::from_utf8_lossy(b).to_string()),
+        (17,Value::Bytes(b)) => h.source = Some(String::from_utf8_lossy(b).to_string()),
+        (32,Value::Varint(n)) => h.replication_timestamp = Some(n as i64),
+        (33,Value::Varint(n)) => h.replication_sequence = Some(n as i64),
+        (34,Value::Bytes(b)) => h.replication_base_url = Some(String::from_utf8_lossy(b).to_string()),
+        _ => {},
+      }
+    }
+    Ok(h)
+  }
+}
+
+enum Value<'a> {
+  Varint(u64),
+  Bytes(&'a [u8]),
+  Fixed,
+}
+
+// minimal protobuf field iterator, just enough for the pbf header
+struct Fields<'a> {
+  buf: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Fields<'a> {
+  fn new(buf: &'a [u8]) -> Self {
+    Self { buf, offset: 0 }
+  }
+  fn varint(&mut self) -> Result<u64,Error> {
+    let mut value = 0u64;
+    for i in 0..10 {
+      let byte = match self.buf.get(self.offset) {
+        Some(b) => *b,
+        None => E::InvalidPbfHeader { message: "unexpected end of data".to_string() }.raise()?,
+      };
+      self.offset += 1;
+      value |= ((byte & 127) as u64) << (7*i);
+      if byte & 128 == 0 { return Ok(value) }
+    }
+    E::InvalidPbfHeader { message: "varint too long".to_string() }.raise()?
+  }
+  fn next_field(&mut self) -> Result<(u64,Value<'a>),Error> {
+    let tag = self.varint()?;
+    let value = match tag & 7 {
+      0 => Value::Varint(self.varint()?),
+      1 => { self.offset += 8; Value::Fixed },
+      2 => {
+        let len = self.varint()? as usize;
+        let end = self.offset + len;
+        if end > self.buf.len() {
+          E::InvalidPbfHeader { message: "unexpected end of data".to_string() }.raise()?
+        }
+        let b = &self.buf[self.offset..end];
+        self.offset = end;
+        Value::Bytes(b)
+      },
+      5 => { self.offset += 4; Value::Fixed },
+      t => E::InvalidPbfHeader { message: format!["unsupported wire type {}", t] }.raise()?,
+    };
+    Ok((tag >> 3, value))
+  }
+}
+
+impl<'a> Iterator for Fields<'a> {
+  type Item = Result<(u64,Value<'a>),Error>;
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.offset >= self.buf.len() { return None }
+    Some(self.next_field())
+  }
+}
+
+pub fn encode_bbox(bbox: &(f64,f64,f64,f64)) -> Result<Vec<u8>,Error> {
+  let mut buf = vec![0u8;8*4];
+  let mut offset = 0;
+  for x in [bbox.0,bbox.1,bbox.2,bbox.3].iter() {
+    offset += x.write_bytes_be(&mut buf[offset..])?;
+  }
+  Ok(buf)
+}
+
+pub fn decode_bbox(buf: &[u8]) -> Result<(f64,f64,f64,f64),Error> {
+  let mut xs = [0f64;4];
+  let mut offset = 0;
+  for x in xs.iter_mut() {
+    let (s,v) = f64::from_bytes_be(&buf[offset..])?;
+    offset += s;
+    *x = v;
+  }
+  Ok((xs[0],xs[1],xs[2],xs[3]))
+}
+
+pub fn encode_u64(n: u64) -> Result<Vec<u8>,Error> {
+  let mut buf = vec![0u8;varint::length(n)];
+  varint::encode(n, &mut buf)?;
+  Ok(buf)
+}
+
+// all metadata records as (name, value) pairs in key order
+pub fn list(lstore: &mut LStore) -> Result<Vec<(String,Vec<u8>)>,Error> {
+  lstore.flush()?;
+  let gt = Key::from(&[META_PREFIX]);
+  let lt = Key::from(&[META_PREFIX+1]);
+  Ok(lstore.iter(lt, gt).map(|(key,value)| {
+    (String::from_utf8_lossy(&key.data[1..]).to_string(), value)
+  }).collect())
+}
+
+// human-readable form of a metadata value for the info command
+pub fn format_value(name: &str, value: &[u8]) -> Result<String,Error> {
+  Ok(match name {
+    "schema_version" | "replication_sequence" => varint::decode(value)?.1.to_string(),
+    "bbox" | "extract_bbox" => {
+      let (w,s,e,n) = decode_bbox(value)?;
+      format!["{},{},{},{}", w, s, e, n]
+    },
+    // the bbox of each coastline row
+    "coastline_rows" => (value.len()/32).to_string(),
+    _ => String::from_utf8_lossy(value).to_string(),
+  })
+}
+
+// utc seconds since the epoch as an ISO 8601 timestamp like the ones in state.txt
+pub fn iso_time(secs: i64) -> String {
+  let days = secs.div_euclid(86400);
+  let t = secs.rem_euclid(86400);
+  // civil date from days since 1970-01-01 (howard hinnant's algorithm)
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+  let doy = doe - (365*yoe + yoe/4 - yoe/100);
+  let mp = (5*doy + 2)/153;
+  let d = doy - (153*mp + 2)/5 + 1;
+  let m = if mp < 10 { mp + 3 } else { mp - 9 };
+  let y = yoe + era*400 + if m <= 2 { 1 } else { 0 };
+  format!["{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, m, d, t/3600, (t/60)%60, t%60]
+}
+
+// seconds since the epoch from an ISO 8601 timestamp like 2021-03-04T00:00:00Z
+pub fn parse_time(s: &str) -> Option<i64> {
+  let num = |i: usize, n: usize| s.get(i..i+n)?.parse::<i64>().ok();
+  let (y,m,d) = (num(0,4)?, num(5,2)?, num(8,2)?);
+  let (hh,mm,ss) = (num(11,2)?, num(14,2)?, num(17,2)?);
+  // days since 1970-01-01 from a civil date, the inverse of iso_time
+  let y = if m <= 2 { y - 1 } else { y };
+  let era = y.div_euclid(400);
+  let yoe = y.rem_euclid(400);
+  let mp = if m > 2 { m - 3 } else { m + 9 };
+  let doy = (153*mp + 2)/5 + d - 1;
+  let doe = yoe*365 + yoe/4 - yoe/100 + doy;
+  let days = era*146097 + doe - 719468;
+  Some(days*86400 + hh*3600 + mm*60 + ss)
+}
+
+pub fn now() -> String {
+  let secs = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or(0);
+  iso_time(secs)
+}
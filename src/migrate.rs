@@ -0,0 +1,111 @@
+use desert::varint;
+use leveldb::iterator::{Iterable,LevelDBIterator};
+use leveldb::options::ReadOptions;
+use crate::{Error,error::IngestErrorKind as E,LStore,Key,ID_PREFIX,meta,meta_key,
+  Coords,Decoded,decode,to_fixed};
+
+// schema version of the records in the level db. databases written before the
+// version was stored used the version 1 layout, and new databases get the
+// current version. a database that a migration didn't finish is refused
+pub fn schema_version(lstore: &mut LStore) -> Result<Option<u64>,Error> {
+  if let Some(buf) = lstore.get(&Key::from(&meta_key("migrating")))? {
+    let to = varint::decode(&buf)?.1;
+    E::IncompleteMigration { from: to.saturating_sub(1), to }.raise()?
+  }
+  Ok(match lstore.get(&Key::from(&meta_key("schema_version")))? {
+    Some(buf) => Some(varint::decode(&buf)?.1),
+    None => {
+      let gt = Key::from(&[ID_PREFIX]);
+      let lt = Key::from(&[ID_PREFIX+1]);
+      if lstore.keys_iter(lt, gt).next().is_some() { Some(1) } else { None }
+    },
+  })
+}
+
+// store the schema version for new databases and refuse to continue if the
+// records were written in a newer layout or in a different one than requested.
+// new databases get the requested version or SCHEMA_VERSION.
+// returns the schema version of the database
+pub fn check(lstore: &mut LStore, requested: Option<u64>) -> Result<u64,Error> {
+  if let Some(version) = requested {
+    if version == 0 || version > meta::LATEST_SCHEMA_VERSION {
+      E::UnsupportedSchemaVersion { version }.raise()?
+    }
+  }
+  match schema_version(lstore)? {
+    Some(version) if version > meta::LATEST_SCHEMA_VERSION => {
+      E::SchemaVersionMismatch { found: version, expected: meta::LATEST_SCHEMA_VERSION }.raise()?
+    },
+    Some(version) => match requested {
+      Some(expected) if expected != version => {
+        E::SchemaVersionMismatch { found: version, expected }.raise()?
+      },
+      _ => Ok(version),
+    },
+    None => {
+      let version = requested.unwrap_or(meta::SCHEMA_VERSION);
+      lstore.put(Key::from(&meta_key("schema_version")), &meta::encode_u64(version)?)?;
+      lstore.flush()?;
+      Ok(version)
+    },
+  }
+}
+
+// rewrite every ID_PREFIX record into the layout of schema version `to`
+// one version at a time and return the resulting schema version
+pub fn migrate(lstore: &mut LStore, to: u64, mut report: impl FnMut() -> ()) -> Result<u64,Error> {
+  let mut version = schema_version(lstore)?.unwrap_or(to);
+  if version > meta::LATEST_SCHEMA_VERSION {
+    E::SchemaVersionMismatch { found: version, expected: meta::LATEST_SCHEMA_VERSION }.raise()?
+  }
+  if to == 0 || to > meta::LATEST_SCHEMA_VERSION {
+    E::UnsupportedSchemaVersion { version: to }.raise()?
+  }
+  if version > to {
+    E::UnsupportedMigration { from: version, to }.raise()?
+  }
+  while version < to {
+    // records are rewritten in place and can't be upgraded twice, so mark the
+    // database until the new version is stored
+    lstore.put(Key::from(&meta_key("migrating")), &meta::encode_u64(version+1)?)?;
+    lstore.flush()?;
+    let gt = Key::from(&[ID_PREFIX]);
+    let lt = Key::from(&[ID_PREFIX+1]);
+    // the level db iterator reads from a snapshot, so writing while iterating is safe
+    let db = lstore.db.clone();
+    let iter = db.iter(ReadOptions::new());
+    iter.seek(&gt);
+    for (key,value) in iter.take_while(move |(k,_)| *k < lt) {
+      if let Some(buf) = upgrade(version, &key.data, &value)? {
+        lstore.put(key, &buf)?;
+      }
+      report();
+    }
+    version += 1;
+    lstore.put(Key::from(&meta_key("schema_version")), &meta::encode_u64(version)?)?;
+    lstore.del(Key::from(&meta_key("migrating")))?;
+    lstore.flush()?;
+  }
+  Ok(version)
+}
+
+// convert a record from version `from` to version `from+1`.
+// returns None when the record is unchanged
+fn upgrade(from: u64, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>,Error> {
+  match from {
+    // node locations go from f32 to fixed-point i32. the rest of the value is the same
+    1 => match decode(key, value, Coords::Float())? {
+      Decoded::Node(node) => {
+        let mut buf = value.to_vec();
+        Coords::Fixed().write(
+          (node.lon as f32, node.lat as f32),
+          (to_fixed(node.lon), to_fixed(node.lat)),
+          &mut buf
+        )?;
+        Ok(Some(buf))
+      },
+      _ => Ok(None),
+    },
+    _ => E::UnsupportedMigration { from, to: from+1 }.raise()?,
+  }
+}
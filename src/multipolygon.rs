@@ -0,0 +1,145 @@
+use crate::{Error,error::IngestErrorKind as E};
+use std::collections::{HashMap,HashSet};
+
+// a closed ring of node ids (first == last) assembled from member ways
+#[derive(Debug,Clone,PartialEq)]
+pub struct Ring {
+  pub refs: Vec<u64>,
+  pub inner: bool,
+}
+
+// join the member ways of a multipolygon (id*2 + inner, as in DecodedRelation)
+// into closed rings at shared endpoints, reversing ways as needed. a ring is
+// inner when it is inside of an odd number of other rings, whatever the member
+// roles say, since those are often missing or wrong. rings come back with each
+// outer followed by its inners. ways that don't close into a ring, rings with
+// fewer than 3 nodes, and missing nodes make the multipolygon invalid.
+// member ways that aren't stored (outside of an extract, say) are skipped
+pub fn assemble(id: u64, members: &[u64], ways: &HashMap<u64,Vec<u64>>,
+  nodes: &HashMap<u64,(f32,f32)>
+) -> Result<Vec<Ring>,Error> {
+  let invalid = |message: String| E::InvalidMultipolygon { id, message };
+  let mut seen = HashSet::new();
+  let mut rings = vec![];
+  let mut open = vec![];
+  for w in members.iter().map(|m| m/2) {
+    if !seen.insert(w) { continue }
+    match ways.get(&w) {
+      Some(refs) if refs.len() > 1 && refs.first() == refs.last() => rings.push(refs.clone()),
+      Some(refs) if refs.len() > 1 => open.push(Some(refs.clone())),
+      Some(_) => invalid(format!["way {} has fewer than 2 nodes", w]).raise()?,
+      None => {},
+    }
+  }
+
+  // ways by their first and last nodes
+  let mut ends: HashMap<u64,Vec<usize>> = HashMap::new();
+  for (i,refs) in open.iter().enumerate() {
+    let refs = refs.as_ref().unwrap();
+    ends.entry(refs[0]).or_default().push(i);
+    ends.entry(refs[refs.len()-1]).or_default().push(i);
+  }
+  for i in 0..open.len() {
+    let mut chain = match open[i].take() {
+      Some(refs) => refs,
+      None => continue,
+    };
+    // extend the end of the chain, then turn it around once to extend the start
+    let mut turned = false;
+    while chain.first() != chain.last() {
+      let end = chain[chain.len()-1];
+      let next = ends.get(&end)
+        .and_then(|js| js.iter().find(|j| open[**j].is_some()))
+        .and_then(|j| open[*j].take());
+      match next {
+        Some(refs) if refs[0] == end => chain.extend_from_slice(&refs[1..]),
+        Some(refs) => chain.extend(refs.iter().rev().skip(1)),
+        None if !turned => {
+          chain.reverse();
+          turned = true;
+        },
+        None => invalid(format!["ways do not close into a ring between nodes {} and {}",
+          chain[0], end]).raise()?,
+      }
+    }
+    rings.push(chain);
+  }
+
+  let mut positions = Vec::with_capacity(rings.len());
+  for refs in rings.iter() {
+    if refs.len() < 4 {
+      invalid(format!["ring at node {} has fewer than 3 nodes", refs[0]]).raise()?
+    }
+    let mut ps = Vec::with_capacity(refs.len());
+    for r in refs.iter() {
+      match nodes.get(r) {
+        Some((lon,lat)) => ps.push((*lon as f64,*lat as f64)),
+        None => invalid(format!["node {} is missing", r]).raise()?,
+      }
+    }
+    positions.push(ps);
+  }
+
+  // rings that each ring is inside of. a ring can only be inside of rings
+  // whose bbox contains its own, which skips most point-in-polygon tests
+  let areas = positions.iter().map(|ps| area(ps)).collect::<Vec<f64>>();
+  let bboxes = positions.iter().map(|ps| bbox(ps)).collect::<Vec<_>>();
+  let sets = rings.iter().map(|refs| refs.iter().copied().collect()).collect::<Vec<HashSet<u64>>>();
+  let parents = (0..rings.len()).map(|i| {
+    (0..rings.len()).filter(|j| {
+      *j != i && areas[*j] > areas[i] && bbox_contains(&bboxes[*j], &bboxes[i])
+        && inside(&rings[i], &positions[i], &sets[*j], &positions[*j])
+    }).collect::<Vec<usize>>()
+  }).collect::<Vec<_>>();
+  let depths = parents.iter().map(|ps| ps.len()).collect::<Vec<usize>>();
+
+  // each outer followed by the inners directly inside of it
+  let mut order = vec![];
+  for i in (0..rings.len()).filter(|i| depths[*i] % 2 == 0) {
+    order.push(i);
+    order.extend((0..rings.len()).filter(|j| {
+      depths[*j] == depths[i]+1 && parents[*j].contains(&i)
+    }));
+  }
+  // georender's member sort joins rings that start on the same node,
+  // so start each ring on a node that no earlier ring starts on
+  let mut starts = HashSet::new();
+  Ok(order.into_iter().map(|i| {
+    let refs = &rings[i];
+    let k = (0..refs.len()-1).find(|k| !starts.contains(&refs[*k])).unwrap_or(0);
+    starts.insert(refs[k]);
+    let mut rotated = refs[k..refs.len()-1].to_vec();
+    rotated.extend_from_slice(&refs[..=k]);
+    Ring { refs: rotated, inner: depths[i] % 2 == 1 }
+  }).collect())
+}
+
+fn area(ps: &[(f64,f64)]) -> f64 {
+  ps.windows(2).fold(0.0, |sum,w| sum + w[0].0*w[1].1 - w[1].0*w[0].1).abs() / 2.0
+}
+
+// west, south, east, north
+fn bbox(ps: &[(f64,f64)]) -> (f64,f64,f64,f64) {
+  ps.iter().fold(
+    (f64::INFINITY,f64::INFINITY,f64::NEG_INFINITY,f64::NEG_INFINITY),
+    |b,(x,y)| (b.0.min(*x), b.1.min(*y), b.2.max(*x), b.3.max(*y))
+  )
+}
+
+fn bbox_contains(a: &(f64,f64,f64,f64), b: &(f64,f64,f64,f64)) -> bool {
+  a.0 <= b.0 && a.1 <= b.1 && b.2 <= a.2 && b.3 <= a.3
+}
+
+// whether ring a is inside of ring b, going by the first node of a that isn't
+// also on b (whose nodes are in b_set) since rings may touch
+fn inside(a: &[u64], a_ps: &[(f64,f64)], b_set: &HashSet<u64>, b_ps: &[(f64,f64)]) -> bool {
+  let (x,y) = match a.iter().zip(a_ps.iter()).find(|(r,_)| !b_set.contains(r)) {
+    Some((_,p)) => *p,
+    None => return false,
+  };
+  // even-odd rule
+  b_ps.windows(2).filter(|w| {
+    let ((x0,y0),(x1,y1)) = (w[0],w[1]);
+    (y0 > y) != (y1 > y) && x < x0 + (y-y0)*(x1-x0)/(y1-y0)
+  }).count() % 2 == 1
+}
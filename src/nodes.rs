@@ -0,0 +1,98 @@
+use std::convert::TryInto;
+use memmap::MmapMut;
+use crate::{Error,encoder::{Coords,to_fixed,from_fixed}};
+
+// bytes per node: lon and lat in the layout of the level db's coords, so that
+// rows built from the cache match rows built from the records. fixed-point
+// (1e-7) values are u32s offset by 2^31 and f32 values are their bits. an
+// all-zero slot means the node is not in the cache
+const SLOT: usize = 8;
+// ids past this are left out of the cache and looked up in leveldb instead.
+// this keeps negative ids (which wrap to huge u64s) from growing the file
+const MAX_ID: u64 = 1<<36;
+
+// dense node location index backed by a memory-mapped file indexed by node id,
+// similar to osmium's dense index. the file is sparse on filesystems that
+// support it, so unused id ranges don't take up disk space
+pub struct NodeCache {
+  file: std::fs::File,
+  mmap: Option<MmapMut>,
+}
+
+impl NodeCache {
+  pub fn open(path: &std::path::Path) -> Result<Self,Error> {
+    let file = std::fs::OpenOptions::new().read(true).write(true).create(true).open(path)?;
+    let mmap = if file.metadata()?.len() > 0 {
+      Some(unsafe { MmapMut::map_mut(&file)? })
+    } else {
+      None
+    };
+    Ok(Self { file, mmap })
+  }
+
+  pub fn get(&self, id: u64, coords: Coords) -> Option<(f32,f32)> {
+    if id >= MAX_ID { return None }
+    let i = (id as usize)*SLOT;
+    let buf = self.mmap.as_ref()?.get(i..i+SLOT)?;
+    let lon = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+    let lat = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+    if lon == 0 && lat == 0 { return None }
+    Some((from_slot(lon,coords),from_slot(lat,coords)))
+  }
+
+  pub fn set(&mut self, id: u64, lon: f64, lat: f64, coords: Coords) -> Result<(),Error> {
+    if id >= MAX_ID { return Ok(()) }
+    let i = (id as usize)*SLOT;
+    if self.len() < i+SLOT {
+      self.grow(i+SLOT)?;
+    }
+    if let Some(mmap) = self.mmap.as_mut() {
+      mmap[i..i+4].copy_from_slice(&to_slot(lon,coords).to_le_bytes());
+      mmap[i+4..i+8].copy_from_slice(&to_slot(lat,coords).to_le_bytes());
+    }
+    Ok(())
+  }
+
+  pub fn remove(&mut self, id: u64) -> () {
+    if id >= MAX_ID { return }
+    let i = (id as usize)*SLOT;
+    if let Some(buf) = self.mmap.as_mut().and_then(|m| m.get_mut(i..i+SLOT)) {
+      buf.fill(0);
+    }
+  }
+
+  pub fn flush(&self) -> Result<(),Error> {
+    if let Some(mmap) = &self.mmap {
+      mmap.flush()?;
+    }
+    Ok(())
+  }
+
+  fn len(&self) -> usize {
+    self.mmap.as_ref().map(|m| m.len()).unwrap_or(0)
+  }
+
+  fn grow(&mut self, min_len: usize) -> Result<(),Error> {
+    let len = min_len.max(self.len()*2).max(1<<20);
+    if let Some(mmap) = self.mmap.take() {
+      mmap.flush()?;
+    }
+    self.file.set_len(len as u64)?;
+    self.mmap = Some(unsafe { MmapMut::map_mut(&self.file)? });
+    Ok(())
+  }
+}
+
+fn to_slot(x: f64, coords: Coords) -> u32 {
+  match coords {
+    Coords::Float() => (x as f32).to_bits(),
+    Coords::Fixed() => ((to_fixed(x) as i64) - (i32::MIN as i64)) as u32,
+  }
+}
+
+fn from_slot(x: u32, coords: Coords) -> f32 {
+  match coords {
+    Coords::Float() => f32::from_bits(x),
+    Coords::Fixed() => from_fixed(((x as i64) + (i32::MIN as i64)) as i32) as f32,
+  }
+}
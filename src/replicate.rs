@@ -0,0 +1,46 @@
+use std::path::{Path,PathBuf};
+use crate::{Error,error::IngestErrorKind as E};
+
+// contents of a replication state.txt file
+#[derive(Debug,Clone,PartialEq)]
+pub struct State {
+  pub sequence: u64,
+  pub timestamp: Option<String>,
+}
+
+impl State {
+  // state files are java properties files, so colons in values are escaped
+  pub fn parse(src: &str) -> Result<Self,Error> {
+    let mut sequence = None;
+    let mut timestamp = None;
+    for line in src.lines() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') { continue }
+      if let Some(i) = line.find('=') {
+        let value = line[i+1..].trim().replace("\\:", ":");
+        match line[..i].trim() {
+          "sequenceNumber" => sequence = Some(value.parse()?),
+          "timestamp" => timestamp = Some(value),
+          _ => {},
+        }
+      }
+    }
+    match sequence {
+      Some(sequence) => Ok(Self { sequence, timestamp }),
+      None => E::ReplicationStateMissingKey { key: "sequenceNumber".to_string() }.raise()?,
+    }
+  }
+  pub fn from_path(file: &Path) -> Result<Self,Error> {
+    Self::parse(&std::fs::read_to_string(file)?)
+  }
+}
+
+// path of a file for a sequence number in the standard directory layout:
+// 1234 with ext "osc.gz" is 000/001/234.osc.gz
+pub fn sequence_path(dir: &Path, sequence: u64, ext: &str) -> PathBuf {
+  let mut file = PathBuf::from(dir);
+  file.push(format!["{:03}", sequence/1_000_000]);
+  file.push(format!["{:03}", (sequence/1_000)%1_000]);
+  file.push(format!["{:03}.{}", sequence%1_000, ext]);
+  file
+}
@@ -64,9 +64,38 @@ pub enum Op {
   Delete(usize),
 }
 
+// a point whose longitude interval runs west > east crosses the antimeridian
+// and is stored as a row on each side of it with the same value
+pub fn split_point(point: &P) -> Vec<P> {
+  match point {
+    (eyros::Coord::Interval(w,e),lat) if w > e => vec![
+      (eyros::Coord::Interval(*w,180.0),lat.clone()),
+      (eyros::Coord::Interval(-180.0,*e),lat.clone()),
+    ],
+    _ => vec![point.clone()],
+  }
+}
+
+fn insert_rows(point: &P, value: &V) -> Vec<eyros::Row<P,V>> {
+  split_point(point).into_iter().map(|p| eyros::Row::Insert(p, value.clone())).collect()
+}
+
+// each side of a split row needs its own delete (see flush). the first delete
+// may find either side, so both cover every longitude
+fn delete_rows(point: &P, id: <V as Value>::Id) -> Vec<eyros::Row<P,V>> {
+  match point {
+    (eyros::Coord::Interval(w,e),lat) if w > e => {
+      let p = (eyros::Coord::Interval(-180.0,180.0),lat.clone());
+      vec![eyros::Row::Delete(p.clone(),id),eyros::Row::Delete(p,id)]
+    },
+    _ => vec![eyros::Row::Delete(point.clone(),id)],
+  }
+}
+
 pub struct EStore {
   pub batch_size: usize,
-  pub batch: Vec<Option<eyros::Row<P,V>>>,
+  // the rows of each operation. cancelled operations are left empty
+  pub batch: Vec<Vec<eyros::Row<P,V>>>,
   pub inserts: HashMap<<V as Value>::Id,Op>,
   pub db: EDB,
   pub sync_interval: usize,
@@ -86,7 +115,7 @@ impl EStore {
   }
   pub fn push_create(&mut self, point: P, value: V) -> () {
     self.inserts.insert(value.get_id(), Op::Insert(self.batch.len()));
-    self.batch.push(Some(eyros::Row::Insert(point,value)));
+    self.batch.push(insert_rows(&point, &value));
   }
   pub async fn create(&mut self, point: P, value: V) -> Result<(),Error> {
     self.push_create(point, value);
@@ -98,27 +127,25 @@ impl EStore {
     let replace = match self.inserts.get_mut(&id) {
       Some(Op::Insert(i)) => {
         // replace existing insert
-        self.batch[*i] = Some(eyros::Row::Insert(new_point.clone(), value.clone()));
+        self.batch[*i] = insert_rows(new_point, value);
         None
       },
       Some(Op::Update(_,i)) => {
         // replace insert, leaving delete
-        self.batch[*i] = Some(eyros::Row::Insert(new_point.clone(), value.clone()));
+        self.batch[*i] = insert_rows(new_point, value);
         None
       },
       Some(Op::Delete(i)) => {
         // leave existing delete, push new insert
         let j = self.batch.len();
-        self.batch[j] = Some(eyros::Row::Insert(new_point.clone(), value.clone()));
+        self.batch.push(insert_rows(new_point, value));
         Some(Op::Update(*i,j))
       },
       None => {
         let i = self.batch.len();
         let j = i+1;
-        self.batch.extend_from_slice(&[
-          Some(eyros::Row::Delete(prev_point.clone(), id)),
-          Some(eyros::Row::Insert(new_point.clone(), value.clone())),
-        ]);
+        self.batch.push(delete_rows(prev_point, id));
+        self.batch.push(insert_rows(new_point, value));
         Some(Op::Update(i,j))
       },
     };
@@ -134,11 +161,11 @@ impl EStore {
   pub fn push_delete(&mut self, point: P, id: <V as Value>::Id) -> () {
     let (rm,replace) = match self.inserts.get(&id) {
       Some(Op::Insert(i)) => {
-        self.batch[*i] = None;
+        self.batch[*i].clear();
         (true,None)
       },
       Some(Op::Update(i,j)) => {
-        self.batch[*j] = None;
+        self.batch[*j].clear();
         (false,Some(Op::Delete(*i)))
       },
       Some(Op::Delete(_)) => {
@@ -147,7 +174,7 @@ impl EStore {
       },
       None => {
         let i = self.batch.len();
-        self.batch.push(Some(eyros::Row::Delete(point,id)));
+        self.batch.push(delete_rows(&point, id));
         (false,Some(Op::Delete(i)))
       },
     };
@@ -162,6 +189,12 @@ impl EStore {
     self.check_flush().await?;
     Ok(())
   }
+  // delete count rows with the same id that all intersect point, such as
+  // duplicates. nothing else may be pending for the id
+  pub fn push_delete_count(&mut self, point: P, id: <V as Value>::Id, count: usize) -> () {
+    self.inserts.insert(id, Op::Delete(self.batch.len()));
+    self.batch.push((0..count).map(|_| eyros::Row::Delete(point.clone(), id)).collect());
+  }
   pub async fn check_flush(&mut self) -> Result<(),Error> {
     if self.batch.len() >= self.batch_size {
       self.flush().await?;
@@ -176,13 +209,27 @@ impl EStore {
       let opts = eyros::BatchOptions::new()
         .error_if_missing(false)
         .rebuild_depth(4);
-      self.db.batch_with_options(&self.batch
-        .iter()
-        .filter(|row| row.is_some())
-        .map(|row| row.as_ref().unwrap().clone())
-        .collect::<Vec<_>>(),
-        &opts
-      ).await?;
+      // eyros keys the deletes of a batch by id and stops looking for an id
+      // once a tree node had rows with it (Merge::remove in eyros/src/tree.rs),
+      // so the nth delete of an id goes into the nth batch. the extra batches
+      // only have deletes and run first
+      let mut batches: Vec<Vec<eyros::Row<P,V>>> = vec![vec![]];
+      let mut deletes: HashMap<<V as Value>::Id,usize> = HashMap::new();
+      for row in self.batch.iter().flatten() {
+        let k = match row {
+          eyros::Row::Delete(_,id) => {
+            let n = deletes.entry(*id).or_insert(0);
+            *n += 1;
+            *n - 1
+          },
+          _ => 0,
+        };
+        if batches.len() <= k { batches.push(vec![]) }
+        batches[k].push(row.clone());
+      }
+      for rows in batches.iter().rev() {
+        self.db.batch_with_options(rows, &opts).await?;
+      }
       self.batch.clear();
       self.inserts.clear();
       self.flush_count += 1;
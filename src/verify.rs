@@ -0,0 +1,243 @@
+use async_std::prelude::*;
+use desert::varint;
+use leveldb::iterator::{Iterable,LevelDBIterator};
+use leveldb::options::ReadOptions;
+use std::collections::{HashMap,HashSet};
+use crate::{Ingest,Phase,Error,Key,Decoded,decode,id_key,backref_key,record_row,retag_record,split_point,coastline,
+  ID_PREFIX,BACKREF_PREFIX};
+
+type P = (eyros::Coord<f32>,eyros::Coord<f32>);
+
+// a discrepancy between the level db and the eyros db.
+// ids are extended ids (id*3 + type)
+#[derive(Debug,Clone,PartialEq)]
+pub enum Problem {
+  // a renderable record has no row in eyros
+  MissingRow { ex_id: u64 },
+  // the eyros row differs from the freshly encoded record
+  MismatchedRow { ex_id: u64 },
+  // more than one eyros row for the same record
+  DuplicateRow { ex_id: u64, count: usize },
+  // an eyros row for a record that is missing or should not be rendered
+  OrphanRow { ex_id: u64 },
+  // a backref from a record that doesn't exist or doesn't reference ex_id
+  DanglingBackref { ex_id: u64, ref_id: u64 },
+  // a record references ex_id but the backref is missing
+  MissingBackref { ex_id: u64, ref_id: u64 },
+}
+
+impl std::fmt::Display for Problem {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Problem::MissingRow { ex_id } => {
+        write![f, "{} is missing from the eyros db", fmt_id(*ex_id)]
+      },
+      Problem::MismatchedRow { ex_id } => {
+        write![f, "{} does not match its eyros row", fmt_id(*ex_id)]
+      },
+      Problem::DuplicateRow { ex_id, count } => {
+        write![f, "{} has {} rows in the eyros db", fmt_id(*ex_id), count]
+      },
+      Problem::OrphanRow { ex_id } => {
+        write![f, "{} has an eyros row but should not", fmt_id(*ex_id)]
+      },
+      Problem::DanglingBackref { ex_id, ref_id } => {
+        write![f, "backref from {} to {} is not referenced", fmt_id(*ref_id), fmt_id(*ex_id)]
+      },
+      Problem::MissingBackref { ex_id, ref_id } => {
+        write![f, "backref from {} to {} is missing", fmt_id(*ref_id), fmt_id(*ex_id)]
+      },
+    }
+  }
+}
+
+fn fmt_id(ex_id: u64) -> String {
+  let t = match ex_id%3 { 0 => "node", 1 => "way", _ => "relation" };
+  format!["{} {}", t, ex_id/3]
+}
+
+impl Ingest {
+  // check that every record in the level db has exactly the eyros row that
+  // process would have written for it, that eyros has no extra rows, and that
+  // backrefs match the references stored in the records. with repair set,
+  // discrepancies are fixed in place
+  pub async fn verify(&mut self, repair: bool) -> Result<Vec<Problem>,Error> {
+    let mut problems = vec![];
+    // records and rows are read straight from the dbs, so write out pending batches first
+    self.flush_verify().await?;
+    let rendered = self.verify_records(repair, &mut problems).await?;
+    if repair { self.flush_verify().await? }
+    self.verify_rows(repair, &rendered, &mut problems).await?;
+    self.verify_backrefs(repair, &mut problems).await?;
+    if repair { self.flush_verify().await? }
+    Ok(problems)
+  }
+
+  // what process would write for a record:
+  // the eyros row (if any) and the (referenced,referencing) backref pairs.
+  // records with raw tags are retagged first, like in process.
+  // broken multipolygons are reported by process, not here
+  pub(crate) async fn expected(&self, decoded: &Decoded) -> Result<(Option<(P,Vec<u8>)>,Vec<(u64,u64)>),Error> {
+    let nodes = self.read_nodes().await;
+    let mut lstore = self.lstore.lock().await;
+    let mut get = |key: &Key| lstore.get(key);
+    let (decoded,_) = retag_record(&mut get, self.coords(), self.filter.as_ref(), self.mapping.as_ref(),
+      self.label_policy.as_ref(), decoded.clone())?;
+    let (row,backrefs,_) = record_row(&mut get, &|id: u64| nodes.as_ref()?.get(id,self.coords()),
+      self.coords(), self.place_other, self.region.as_ref(), &decoded)?;
+    Ok((row,backrefs))
+  }
+
+  // returns the ids of the records that should have a row
+  async fn verify_records(&mut self, repair: bool, problems: &mut Vec<Problem>) -> Result<HashSet<u64>,Error> {
+    let mut rendered = HashSet::new();
+    let gt = Key::from(&[ID_PREFIX]);
+    let lt = Key::from(&[ID_PREFIX+1]);
+    let db = self.lstore.lock().await.db.clone();
+    let iter = db.iter(ReadOptions::new());
+    iter.seek(&gt);
+    for (key,value) in iter.take_while(move |(k,_)| *k < lt) {
+      let decoded = decode(&key.data, &value, self.coords())?;
+      let ex_id = decoded.ex_id();
+      let (row,backrefs) = self.expected(&decoded).await?;
+      if let Some((point,encoded)) = row {
+        rendered.insert(ex_id);
+        let rows = {
+          let mut estore = self.estore.lock().await;
+          let mut stream = estore.db.query(&bounds(&point)).await?;
+          let mut rows = vec![];
+          while let Some(result) = stream.next().await {
+            let (pt,v) = result?;
+            if eyros::Value::get_id(&v) == ex_id { rows.push((pt,v.data)) }
+          }
+          rows
+        };
+        // rows across the antimeridian are split in two
+        let parts = split_point(&point);
+        let problem = match rows.len() {
+          0 => Some(Problem::MissingRow { ex_id }),
+          count if count > parts.len() => Some(Problem::DuplicateRow { ex_id, count }),
+          count if count == parts.len() && parts.iter().all(|p| {
+            rows.iter().any(|(pt,data)| pt == p && *data == encoded)
+          }) => None,
+          _ => Some(Problem::MismatchedRow { ex_id }),
+        };
+        if let Some(p) = problem {
+          if repair {
+            let mut estore = self.estore.lock().await;
+            if let Some(rm) = rows.iter().map(|(pt,_)| pt.clone()).reduce(|a,b| merge(&a,&b)) {
+              estore.push_delete_count(rm, ex_id, rows.len());
+            }
+            estore.push_create(point, encoded.into());
+            estore.check_flush().await?;
+          }
+          problems.push(p);
+        }
+      }
+      let mut lstore = self.lstore.lock().await;
+      for (a,b) in backrefs {
+        let bkey = Key::from(&backref_key(a,b)?);
+        if lstore.get(&bkey)?.is_none() {
+          if repair { lstore.put(bkey, &vec![])? }
+          problems.push(Problem::MissingBackref { ex_id: a, ref_id: b });
+        }
+      }
+      drop(lstore);
+      if let Some(f) = self.reporter.lock().await.as_mut() {
+        f(Phase::Verify(), Ok(()));
+      }
+    }
+    Ok(rendered)
+  }
+
+  async fn verify_rows(&mut self, repair: bool, rendered: &HashSet<u64>, problems: &mut Vec<Problem>)
+  -> Result<(),Error> {
+    // the bounds and number of rows of each id without a rendered record
+    let mut orphans: HashMap<u64,(P,usize)> = HashMap::new();
+    {
+      let mut estore = self.estore.lock().await;
+      let mut stream = estore.db.query(&((-180.0,-90.0),(180.0,90.0))).await?;
+      while let Some(result) = stream.next().await {
+        let (pt,v) = result?;
+        let ex_id = eyros::Value::get_id(&v);
+        // coastline rows have no record
+        if coastline::is_coastline_id(ex_id) || rendered.contains(&ex_id) { continue }
+        match orphans.get_mut(&ex_id) {
+          Some((rm,count)) => {
+            *rm = merge(rm, &pt);
+            *count += 1;
+          },
+          None => { orphans.insert(ex_id, (pt,1)); },
+        }
+      }
+    }
+    let mut orphans = orphans.into_iter().collect::<Vec<_>>();
+    orphans.sort_by_key(|(ex_id,_)| *ex_id);
+    for (ex_id,(pt,count)) in orphans {
+      if repair {
+        let mut estore = self.estore.lock().await;
+        estore.push_delete_count(pt, ex_id, count);
+        estore.check_flush().await?;
+      }
+      problems.push(Problem::OrphanRow { ex_id });
+    }
+    Ok(())
+  }
+
+  async fn verify_backrefs(&mut self, repair: bool, problems: &mut Vec<Problem>) -> Result<(),Error> {
+    let gt = Key::from(&[BACKREF_PREFIX]);
+    let lt = Key::from(&[BACKREF_PREFIX+1]);
+    let db = self.lstore.lock().await.db.clone();
+    let iter = db.keys_iter(ReadOptions::new());
+    iter.seek(&gt);
+    for key in iter.take_while(move |k| *k < lt) {
+      let (s,ex_id) = varint::decode(&key.data[1..])?;
+      let (_,ref_id) = varint::decode(&key.data[1+s..])?;
+      let exists = self.lstore.lock().await.get(&Key::from(&id_key(ex_id)?))?.is_some();
+      let valid = exists && match (ex_id%3, ref_id%3) {
+        (0,1) => self.get_refs(ref_id).await?.contains(&(ex_id/3)),
+        // way members of routes are kept with the node and relation members
+        (1,2) => self.get_refs(ref_id).await?.contains(&(ex_id/3))
+          || self.get_member_refs(ref_id).await?.contains(&ex_id),
+        (0,2) | (2,2) => self.get_member_refs(ref_id).await?.contains(&ex_id),
+        _ => false,
+      };
+      if !valid {
+        if repair { self.lstore.lock().await.del(key)? }
+        problems.push(Problem::DanglingBackref { ex_id, ref_id });
+      }
+    }
+    Ok(())
+  }
+
+  async fn flush_verify(&mut self) -> Result<(),Error> {
+    self.lstore.lock().await.flush()?;
+    let mut estore = self.estore.lock().await;
+    estore.flush().await?;
+    estore.sync().await?;
+    Ok(())
+  }
+}
+
+// a longitude interval with west > east crosses the antimeridian,
+// so its bounds cover every longitude
+fn bounds(point: &P) -> ((f32,f32),(f32,f32)) {
+  fn range(c: &eyros::Coord<f32>) -> (f32,f32) {
+    match c {
+      eyros::Coord::Scalar(x) => (*x,*x),
+      eyros::Coord::Interval(x,y) if x > y => (-180.0,180.0),
+      eyros::Coord::Interval(x,y) => (*x,*y),
+    }
+  }
+  let (x,y) = (range(&point.0),range(&point.1));
+  ((x.0,y.0),(x.1,y.1))
+}
+
+fn merge(a: &P, b: &P) -> P {
+  let ((ax0,ay0),(ax1,ay1)) = bounds(a);
+  let ((bx0,by0),(bx1,by1)) = bounds(b);
+  (
+    eyros::Coord::Interval(ax0.min(bx0),ax1.max(bx1)),
+    eyros::Coord::Interval(ay0.min(by0),ay1.max(by1)),
+  )
+}
@@ -0,0 +1,294 @@
+use std::io::BufRead;
+use quick_xml::events::{Event,BytesStart};
+use crate::{Error,error::IngestErrorKind as E,meta,encoder::to_fixed};
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Element {
+  Node(Node),
+  Way(Way),
+  Relation(Relation),
+}
+#[derive(Debug,Clone,PartialEq)]
+pub struct Node {
+  pub id: u64,
+  pub lon: f64,
+  pub lat: f64,
+  pub tags: Vec<(String,String)>,
+  pub info: Option<o5m_stream::Info>,
+}
+#[derive(Debug,Clone,PartialEq)]
+pub struct Way {
+  pub id: u64,
+  pub refs: Vec<u64>,
+  pub tags: Vec<(String,String)>,
+  pub info: Option<o5m_stream::Info>,
+}
+#[derive(Debug,Clone,PartialEq)]
+pub struct Relation {
+  pub id: u64,
+  pub members: Vec<Member>,
+  pub tags: Vec<(String,String)>,
+  pub info: Option<o5m_stream::Info>,
+}
+#[derive(Debug,Clone,PartialEq)]
+pub struct Member {
+  pub id: u64,
+  pub member_type: MemberType,
+  pub role: String,
+}
+#[derive(Debug,Clone,PartialEq)]
+pub enum MemberType {
+  Node(),
+  Way(),
+  Relation(),
+}
+#[derive(Debug,Clone,PartialEq)]
+pub enum Action {
+  Create(),
+  Modify(),
+  Delete(),
+}
+
+impl Element {
+  pub fn get_id(&self) -> u64 {
+    match self {
+      Element::Node(node) => node.id,
+      Element::Way(way) => way.id,
+      Element::Relation(relation) => relation.id,
+    }
+  }
+  pub fn get_tags(&self) -> Vec<(&str,&str)> {
+    let tags = match self {
+      Element::Node(node) => &node.tags,
+      Element::Way(way) => &way.tags,
+      Element::Relation(relation) => &relation.tags,
+    };
+    tags.iter().map(|(k,v)| (k.as_str(),v.as_str())).collect()
+  }
+  // convert into the o5m representation used by the changeset machinery.
+  // deletes carry no data no matter what the osmChange file included
+  pub fn into_dataset(self, action: &Action) -> o5m_stream::Dataset {
+    let is_rm = action == &Action::Delete();
+    match self {
+      Element::Node(node) => o5m_stream::Dataset::Node(o5m_stream::Node {
+        id: node.id,
+        info: node.info,
+        data: if is_rm { None } else {
+          Some(o5m_stream::NodeData {
+            longitude: to_fixed(node.lon),
+            latitude: to_fixed(node.lat),
+          })
+        },
+        tags: node.tags.into_iter().collect(),
+      }),
+      Element::Way(way) => o5m_stream::Dataset::Way(o5m_stream::Way {
+        id: way.id,
+        info: way.info,
+        data: if is_rm { None } else {
+          Some(o5m_stream::WayData { refs: way.refs })
+        },
+        tags: way.tags.into_iter().collect(),
+      }),
+      Element::Relation(relation) => o5m_stream::Dataset::Relation(o5m_stream::Relation {
+        id: relation.id,
+        info: relation.info,
+        data: if is_rm { None } else {
+          Some(o5m_stream::RelationData {
+            members: relation.members.into_iter().map(|m| o5m_stream::RelationMember {
+              id: m.id,
+              element_type: match m.member_type {
+                MemberType::Node() => o5m_stream::ElementType::Node(),
+                MemberType::Way() => o5m_stream::ElementType::Way(),
+                MemberType::Relation() => o5m_stream::ElementType::Relation(),
+              },
+              role: m.role,
+            }).collect()
+          })
+        },
+        tags: relation.tags.into_iter().collect(),
+      }),
+    }
+  }
+  fn push_tag(&mut self, tag: (String,String)) -> () {
+    match self {
+      Element::Node(node) => node.tags.push(tag),
+      Element::Way(way) => way.tags.push(tag),
+      Element::Relation(relation) => relation.tags.push(tag),
+    }
+  }
+}
+
+// streaming reader for osm xml and osmChange documents
+// that yields one element at a time
+pub struct Reader<R: BufRead> {
+  reader: quick_xml::Reader<R>,
+  buf: Vec<u8>,
+  current: Option<Element>,
+  action: Option<Action>,
+}
+
+impl<R: BufRead> Reader<R> {
+  pub fn new(r: R) -> Self {
+    let mut reader = quick_xml::Reader::from_reader(r);
+    reader.trim_text(true);
+    reader.expand_empty_elements(true);
+    // osmconvert tolerates mismatched closing tags, so fixtures written by hand
+    // (like tests/data/0/ingest.xml) have a few of them
+    reader.check_end_names(false);
+    Self { reader, buf: vec![], current: None, action: None }
+  }
+
+  // elements outside of a create, modify, or delete block are treated as modify
+  pub fn next_change(&mut self) -> Result<Option<(Action,Element)>,Error> {
+    Ok(self.next_element()?.map(|element| {
+      (self.action.clone().unwrap_or(Action::Modify()), element)
+    }))
+  }
+
+  pub fn next_element(&mut self) -> Result<Option<Element>,Error> {
+    loop {
+      self.buf.clear();
+      match self.reader.read_event(&mut self.buf)? {
+        Event::Start(e) => match e.name() {
+          b"create" => self.action = Some(Action::Create()),
+          b"modify" => self.action = Some(Action::Modify()),
+          b"delete" => self.action = Some(Action::Delete()),
+          b"node" => {
+            // deleted nodes in osmChange files are not required to have coordinates
+            let (lon,lat) = match self.action {
+              Some(Action::Delete()) => (0.0,0.0),
+              _ => (
+                required(&self.reader, &e, b"lon")?.parse()?,
+                required(&self.reader, &e, b"lat")?.parse()?,
+              ),
+            };
+            self.current = Some(Element::Node(Node {
+              id: parse_id(&self.reader, &e)?,
+              lon,
+              lat,
+              tags: vec![],
+              info: parse_info(&self.reader, &e)?,
+            }));
+          },
+          b"way" => {
+            self.current = Some(Element::Way(Way {
+              id: parse_id(&self.reader, &e)?,
+              refs: vec![],
+              tags: vec![],
+              info: parse_info(&self.reader, &e)?,
+            }));
+          },
+          b"relation" => {
+            self.current = Some(Element::Relation(Relation {
+              id: parse_id(&self.reader, &e)?,
+              members: vec![],
+              tags: vec![],
+              info: parse_info(&self.reader, &e)?,
+            }));
+          },
+          b"tag" => {
+            let k = required(&self.reader, &e, b"k")?;
+            let v = required(&self.reader, &e, b"v")?;
+            if let Some(element) = self.current.as_mut() {
+              element.push_tag((k,v));
+            }
+          },
+          b"nd" => {
+            let r = required(&self.reader, &e, b"ref")?.parse::<i64>()? as u64;
+            if let Some(Element::Way(way)) = self.current.as_mut() {
+              way.refs.push(r);
+            }
+          },
+          b"member" => {
+            let member_type = match required(&self.reader, &e, b"type")?.as_str() {
+              "node" => MemberType::Node(),
+              "way" => MemberType::Way(),
+              "relation" => MemberType::Relation(),
+              x => E::XmlInvalidAttribute {
+                element: "member".to_string(),
+                attribute: "type".to_string(),
+                value: x.to_string(),
+              }.raise()?,
+            };
+            let member = Member {
+              id: required(&self.reader, &e, b"ref")?.parse::<i64>()? as u64,
+              member_type,
+              role: optional(&self.reader, &e, b"role")?.unwrap_or_default(),
+            };
+            if let Some(Element::Relation(relation)) = self.current.as_mut() {
+              relation.members.push(member);
+            }
+          },
+          _ => {},
+        },
+        Event::End(e) => match e.name() {
+          b"create" | b"modify" | b"delete" => self.action = None,
+          b"node" | b"way" | b"relation" => {
+            if let Some(element) = self.current.take() {
+              return Ok(Some(element));
+            }
+          },
+          _ => {},
+        },
+        Event::Eof => return Ok(None),
+        _ => {},
+      }
+    }
+  }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+  type Item = Result<Element,Error>;
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_element().transpose()
+  }
+}
+
+fn optional<R: BufRead>(reader: &quick_xml::Reader<R>, e: &BytesStart, key: &[u8])
+-> Result<Option<String>,Error> {
+  for attr in e.attributes() {
+    let attr = attr?;
+    if attr.key == key {
+      return Ok(Some(attr.unescape_and_decode_value(reader)?));
+    }
+  }
+  Ok(None)
+}
+
+fn required<R: BufRead>(reader: &quick_xml::Reader<R>, e: &BytesStart, key: &[u8])
+-> Result<String,Error> {
+  match optional(reader, e, key)? {
+    Some(value) => Ok(value),
+    None => E::XmlMissingAttribute {
+      element: String::from_utf8_lossy(e.name()).to_string(),
+      attribute: String::from_utf8_lossy(key).to_string(),
+    }.raise()?,
+  }
+}
+
+fn parse_id<R: BufRead>(reader: &quick_xml::Reader<R>, e: &BytesStart) -> Result<u64,Error> {
+  // negative ids (from editors like josm) wrap the same way as in Element::from_osmpbf
+  Ok(required(reader, e, b"id")?.parse::<i64>()? as u64)
+}
+
+// version, timestamp, changeset, uid, and user attributes if there are any
+fn parse_info<R: BufRead>(reader: &quick_xml::Reader<R>, e: &BytesStart)
+-> Result<Option<o5m_stream::Info>,Error> {
+  let mut info = o5m_stream::Info::new();
+  info.version = optional(reader, e, b"version")?.map(|x| x.parse()).transpose()?;
+  info.timestamp = match optional(reader, e, b"timestamp")? {
+    Some(t) => match meta::parse_time(&t) {
+      Some(x) => Some(x),
+      None => E::XmlInvalidAttribute {
+        element: String::from_utf8_lossy(e.name()).to_string(),
+        attribute: "timestamp".to_string(),
+        value: t,
+      }.raise()?,
+    },
+    None => None,
+  };
+  info.changeset = optional(reader, e, b"changeset")?.map(|x| x.parse()).transpose()?;
+  info.uid = optional(reader, e, b"uid")?.map(|x| x.parse()).transpose()?;
+  info.user = optional(reader, e, b"user")?;
+  Ok(if info == o5m_stream::Info::new() { None } else { Some(info) })
+}
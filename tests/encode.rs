@@ -1,15 +1,15 @@
-use peermaps_ingest::encoder::{
-  encode_osmpbf,
-  decode,Decoded,DecodedNode,DecodedWay,DecodedRelation
-};
+use peermaps_ingest::{xml,encoder::{
+  Element,EncodeOptions,encode_element,encode_element_members,decode_members,
+  decode,Coords,Decoded,DecodedNode,DecodedWay,DecodedRelation
+}};
 
 type Error = Box<dyn std::error::Error+Send+Sync>;
 
 #[test]
 fn encoder() -> Result<(),Error> {
   osmpbf::ElementReader::from_path("tests/data/0/node.pbf")?.for_each(|element| {
-    let (key,value) = encode_osmpbf(&element).unwrap();
-    let decoded = decode(&key,&value).unwrap();
+    let (key,value) = encode_element(&Element::from_osmpbf(&element).unwrap(),&EncodeOptions::new(Coords::Float())).unwrap();
+    let decoded = decode(&key,&value,Coords::Float()).unwrap();
     assert_eq![decoded, Decoded::Node(DecodedNode {
       id: 1312,
       feature_type: 711, // place.other
@@ -19,8 +19,8 @@ fn encoder() -> Result<(),Error> {
     })];
   })?;
   osmpbf::ElementReader::from_path("tests/data/0/way.pbf")?.for_each(|element| {
-    let (key,value) = encode_osmpbf(&element).unwrap();
-    let decoded = decode(&key,&value).unwrap();
+    let (key,value) = encode_element(&Element::from_osmpbf(&element).unwrap(),&EncodeOptions::new(Coords::Float())).unwrap();
+    let decoded = decode(&key,&value,Coords::Float()).unwrap();
     assert_eq![decoded, Decoded::Way(DecodedWay {
       id: 555,
       feature_type: 47, // amenity.cafe
@@ -30,8 +30,8 @@ fn encoder() -> Result<(),Error> {
     })];
   })?;
   osmpbf::ElementReader::from_path("tests/data/0/relation.pbf")?.for_each(|element| {
-    let (key,value) = encode_osmpbf(&element).unwrap();
-    let decoded = decode(&key,&value).unwrap();
+    let (key,value) = encode_element(&Element::from_osmpbf(&element).unwrap(),&EncodeOptions::new(Coords::Float())).unwrap();
+    let decoded = decode(&key,&value,Coords::Float()).unwrap();
     assert_eq![decoded, Decoded::Relation(DecodedRelation {
       id: 700,
       feature_type: 643, // natural.water
@@ -42,3 +42,245 @@ fn encoder() -> Result<(),Error> {
   })?;
   Ok(())
 }
+
+#[test]
+fn xml_encoder() -> Result<(),Error> {
+  let open = |file: &str| -> Result<_,Error> {
+    Ok(xml::Reader::new(std::io::BufReader::new(std::fs::File::open(file)?)))
+  };
+  for element in open("tests/data/0/node.xml")? {
+    let (key,value) = encode_element(&Element::from_xml(&element?),&EncodeOptions::new(Coords::Float()))?;
+    assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Node(DecodedNode {
+      id: 1312,
+      feature_type: 711, // place.other
+      lon: 13.0,
+      lat: 37.0,
+      labels: vec![0],
+    })];
+  }
+  for element in open("tests/data/0/way.xml")? {
+    let (key,value) = encode_element(&Element::from_xml(&element?),&EncodeOptions::new(Coords::Float()))?;
+    assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Way(DecodedWay {
+      id: 555,
+      feature_type: 47, // amenity.cafe
+      refs: vec![600,601,602],
+      labels: vec![0],
+      is_area: false,
+    })];
+  }
+  for element in open("tests/data/0/relation.xml")? {
+    let (key,value) = encode_element(&Element::from_xml(&element?),&EncodeOptions::new(Coords::Float()))?;
+    assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Relation(DecodedRelation {
+      id: 700,
+      feature_type: 643, // natural.water
+      members: vec![701*2+0,702*2+1,703*2+0],
+      labels: "\x0e=lake whatever\x05x=...\x00".into(),
+      is_area: false,
+    })];
+  }
+  Ok(())
+}
+
+#[test]
+fn fixed_encoder() -> Result<(),Error> {
+  osmpbf::ElementReader::from_path("tests/data/0/node.pbf")?.for_each(|element| {
+    let (key,value) = encode_element(&Element::from_osmpbf(&element).unwrap(),&EncodeOptions::new(Coords::Fixed())).unwrap();
+    // 1e-7 degree integers
+    assert_eq![&value[0..8], &[0x07,0xbf,0xa4,0x80,0x16,0x0d,0xc0,0x80]];
+    let decoded = decode(&key,&value,Coords::Fixed()).unwrap();
+    assert_eq![decoded, Decoded::Node(DecodedNode {
+      id: 1312,
+      feature_type: 711, // place.other
+      lon: 13.0,
+      lat: 37.0,
+      labels: vec![0],
+    })];
+  })?;
+  let file = std::fs::File::open("tests/data/0/node.xml")?;
+  for element in xml::Reader::new(std::io::BufReader::new(file)) {
+    let (_,value) = encode_element(&Element::from_xml(&element?),&EncodeOptions::new(Coords::Fixed()))?;
+    assert_eq![&value[0..8], &[0x07,0xbf,0xa4,0x80,0x16,0x0d,0xc0,0x80]];
+  }
+  Ok(())
+}
+
+#[test]
+fn filter_encoder() -> Result<(),Error> {
+  use peermaps_ingest::filter::Filter;
+  let filter = Filter::parse("# roads and benches but not private roads
+    w/highway
+    n/amenity=bench,waste_*
+    !highway=private
+    !name:*=secret
+  ")?;
+  assert![filter.matches(1, &[("highway","residential")])];
+  assert![!filter.matches(0, &[("highway","residential")])];
+  assert![!filter.matches(1, &[("highway","private")])];
+  assert![filter.matches(0, &[("amenity","bench")])];
+  assert![filter.matches(0, &[("amenity","waste_basket")])];
+  assert![!filter.matches(0, &[("amenity","cafe")])];
+  assert![!filter.matches(0, &[("amenity","bench"),("name:en","secret")])];
+  assert![!filter.matches(2, &[("natural","water")])];
+  let mut filter = Filter::new();
+  filter.push("!nw/building")?;
+  assert![filter.matches(2, &[("building","yes")])];
+  assert![!filter.matches(1, &[("building","yes")])];
+  assert![filter.push("w/").is_err()];
+  assert![filter.push("amenity=").is_err()];
+
+  let open = |file: &str| -> Result<_,Error> {
+    Ok(xml::Reader::new(std::io::BufReader::new(std::fs::File::open(file)?)))
+  };
+  let mut cafes = Filter::new();
+  cafes.push("w/amenity=cafe")?;
+  let mut nodes = Filter::new();
+  nodes.push("n/amenity")?;
+  for element in open("tests/data/0/way.xml")? {
+    let element = element?;
+    let (key,value) = encode_element(&Element::from_xml(&element),&EncodeOptions { filter: Some(&cafes), ..EncodeOptions::new(Coords::Float()) })?;
+    match decode(&key,&value,Coords::Float())? {
+      Decoded::Way(way) => assert_eq![way.feature_type, 47], // amenity.cafe
+      _ => panic!["expected a way"],
+    }
+    // filtered out ways are still stored, but without a feature type
+    let (key,value) = encode_element(&Element::from_xml(&element),&EncodeOptions { filter: Some(&nodes), ..EncodeOptions::new(Coords::Float()) })?;
+    assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Way(DecodedWay {
+      id: 555,
+      feature_type: 711, // place.other
+      refs: vec![600,601,602],
+      labels: vec![0],
+      is_area: false,
+    })];
+  }
+  Ok(())
+}
+
+#[test]
+fn mapping_encoder() -> Result<(),Error> {
+  use peermaps_ingest::mapping::Mapping;
+  let types = georender_pack::osm_types::get_types();
+  let mapping = Mapping::parse("# cafes as a new area type, lakes as wetlands
+    w/amenity=cafe 1000 60 area
+    r/natural=water natural.wetland line
+    * 2000
+  ")?;
+  assert_eq![mapping.other(), 2000];
+  assert_eq![mapping.feature_type(1, &[("amenity","cafe")], 47), (1000,Some(true))];
+  // georender's type wins over a rule with a lower priority
+  let low = Mapping::parse("amenity=cafe 1000 10")?;
+  assert_eq![low.feature_type(1, &[("amenity","cafe")], 47), (47,None)];
+  assert![Mapping::parse("amenity=cafe").is_err()];
+  assert![Mapping::parse("amenity=cafe not.a.type").is_err()];
+  assert![Mapping::parse("amenity=cafe 1000 area 60").is_err()];
+  assert![Mapping::parse("!amenity=cafe 1000").is_err()];
+
+  let open = |file: &str| -> Result<_,Error> {
+    Ok(xml::Reader::new(std::io::BufReader::new(std::fs::File::open(file)?)))
+  };
+  for element in open("tests/data/0/node.xml")? {
+    let (key,value) = encode_element(&Element::from_xml(&element?),&EncodeOptions { mapping: Some(&mapping), ..EncodeOptions::new(Coords::Float()) })?;
+    assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Node(DecodedNode {
+      id: 1312,
+      feature_type: 2000,
+      lon: 13.0,
+      lat: 37.0,
+      labels: vec![0],
+    })];
+  }
+  for element in open("tests/data/0/way.xml")? {
+    let (key,value) = encode_element(&Element::from_xml(&element?),&EncodeOptions { mapping: Some(&mapping), ..EncodeOptions::new(Coords::Float()) })?;
+    assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Way(DecodedWay {
+      id: 555,
+      feature_type: 1000,
+      refs: vec![600,601,602],
+      labels: vec![0],
+      is_area: true,
+    })];
+  }
+  for element in open("tests/data/0/relation.xml")? {
+    let (key,value) = encode_element(&Element::from_xml(&element?),&EncodeOptions { mapping: Some(&mapping), ..EncodeOptions::new(Coords::Float()) })?;
+    assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Relation(DecodedRelation {
+      id: 700,
+      feature_type: *types.get("natural.wetland").unwrap(),
+      members: vec![701*2+0,702*2+1,703*2+0],
+      labels: "\x0e=lake whatever\x05x=...\x00".into(),
+      is_area: false,
+    })];
+  }
+  Ok(())
+}
+
+#[test]
+fn label_encoder() -> Result<(),Error> {
+  use peermaps_ingest::label::{LabelPolicy,Normalization,encode_labels,decode_labels};
+  let labels = encode_labels(&["=Cafe\u{301}".into(),"en=Cafe".into(),"de=Kaffee".into()])?;
+  let policy = LabelPolicy {
+    languages: LabelPolicy::parse_languages("de,name")?,
+    normalization: Some(Normalization::from_name("NFC")?),
+    ..LabelPolicy::new()
+  };
+  assert_eq![decode_labels(&policy.select(&labels)?)?, vec!["de=Kaffee","=Caf\u{e9}"]];
+  let policy = LabelPolicy {
+    languages: vec!["fr".into()],
+    fallback: LabelPolicy::parse_languages("it,en,*")?,
+    ..LabelPolicy::new()
+  };
+  assert_eq![decode_labels(&policy.select(&labels)?)?, vec!["en=Cafe"]];
+  let policy = LabelPolicy { languages: vec!["fr".into()], ..LabelPolicy::new() };
+  assert_eq![policy.select(&labels)?, vec![0]];
+  let policy = LabelPolicy {
+    languages: vec!["*".into()],
+    max_labels: Some(2),
+    ..LabelPolicy::new()
+  };
+  assert_eq![decode_labels(&policy.select(&labels)?)?, vec!["=Cafe\u{301}","en=Cafe"]];
+  assert![LabelPolicy::parse_languages("en,,de").is_err()];
+  assert![Normalization::from_name("nfd").is_err()];
+  assert_eq![Normalization::from_name("NFKC")?, Normalization::Nfkc];
+  assert![decode_labels(&[5,b'e',b'n',b'=',0]).is_err()];
+
+  let open = |file: &str| -> Result<_,Error> {
+    Ok(xml::Reader::new(std::io::BufReader::new(std::fs::File::open(file)?)))
+  };
+  let policy = LabelPolicy {
+    languages: vec!["en".into()],
+    fallback: LabelPolicy::parse_languages("name")?,
+    ..LabelPolicy::new()
+  };
+  for element in open("tests/data/0/relation.xml")? {
+    let (key,value) = encode_element(&Element::from_xml(&element?),&EncodeOptions { policy: Some(&policy), ..EncodeOptions::new(Coords::Float()) })?;
+    assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Relation(DecodedRelation {
+      id: 700,
+      feature_type: 643, // natural.water
+      members: vec![701*2+0,702*2+1,703*2+0],
+      labels: "\x0e=lake whatever\x00".into(),
+      is_area: false,
+    })];
+  }
+  Ok(())
+}
+
+#[test]
+fn route_encoder() -> Result<(),Error> {
+  let file = std::fs::File::open("tests/data/0/route.xml")?;
+  for element in xml::Reader::new(std::io::BufReader::new(file)) {
+    let element = element?;
+    let members = encode_element_members(&Element::from_xml(&element))?;
+    if let xml::Element::Relation(_) = element {
+      // every way of a route is kept in order, whatever its role
+      let (_,value) = members.unwrap();
+      assert_eq![decode_members(&value)?, vec![(9100*3+0)*4, (800*3+1)*4, (801*3+1)*4]];
+      let (key,value) = encode_element(&Element::from_xml(&element),&EncodeOptions::new(Coords::Float()))?;
+      assert_eq![decode(&key,&value,Coords::Float())?, Decoded::Relation(DecodedRelation {
+        id: 810,
+        feature_type: *georender_pack::osm_types::get_types().get("route.bus").unwrap(),
+        members: vec![],
+        labels: "\x03=10\x00".into(),
+        is_area: false,
+      })];
+    } else {
+      assert![members.is_none()];
+    }
+  }
+  Ok(())
+}
@@ -1,5 +1,5 @@
 use peermaps_ingest::{Ingest,Key,EStore,LStore};
-use leveldb::{database::Database,options::Options};
+use leveldb::{database::Database,options::{Options,ReadOptions},iterator::{Iterable,LevelDBIterator}};
 use async_std::{prelude::*,fs::File};
 use tempfile::Builder as Tmpfile;
 use eyros::{Coord as C};
@@ -11,44 +11,15 @@ type Error = Box<dyn std::error::Error+Send+Sync>;
 #[async_std::test]
 async fn ingest() -> Result<(),Error> {
   let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
-  let mut ldb_dir = std::path::PathBuf::from(&dir.path());
-  ldb_dir.push("ldb");
-  let mut edb_dir = std::path::PathBuf::from(&dir.path());
-  edb_dir.push("edb");
 
   let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
   pbf_file.push("tests/data/0/ingest.pbf");
 
-  let mut ingest = Ingest::new(
-    LStore::new(open(std::path::Path::new(&ldb_dir))?),
-    EStore::new(eyros::open_from_path2(&std::path::Path::new(&edb_dir)).await?)
-  );
+  let mut ingest = setup(dir.path(), "ingest").await?;
   ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
   ingest.process().await;
   {
-    let mut estore = ingest.estore.lock().await;
-    let mut stream = estore.db.query(&((3.0,-15.0),(15.0,45.0))).await?;
-    let mut results = vec![];
-    while let Some(result) = stream.next().await {
-      let (pt,v) = result?;
-      results.push((pt,georender_pack::decode(&v.data)?));
-    }
-    results.sort_by(|a,b| {
-      let a_id = get_id(&a.1);
-      let b_id = get_id(&b.1);
-      fn get_id(x: &georender_pack::Feature) -> u64 {
-        match x {
-          georender_pack::Feature::Point(x) => x.id,
-          georender_pack::Feature::Line(x) => x.id,
-          georender_pack::Feature::Area(x) => x.id,
-        }
-      }
-      match (a_id < b_id, a_id > b_id) {
-        (true,_) => std::cmp::Ordering::Less,
-        (_,true) => std::cmp::Ordering::Greater,
-        (false,false) => std::cmp::Ordering::Equal,
-      }
-    });
+    let results = query(&ingest, &((3.0,-15.0),(15.0,45.0))).await?;
     let ex_positions = vec![ 13.00,37.00, 13.01,37.01, 13.02,37.00 ];
     let ex_cells = earcutr::earcut(&ex_positions.iter()
       .map(|p| *p as f64).collect(), &vec![], 2);
@@ -93,35 +64,17 @@ async fn ingest() -> Result<(),Error> {
     o5c_file.push("tests/data/0/changeset0.o5c");
     ingest.changeset(Box::new(File::open(&o5c_file).await?)).await?;
 
-    let mut estore = ingest.estore.lock().await;
-    let mut stream = estore.db.query(&((3.0,-15.0),(15.0,45.0))).await?;
-    let mut results = vec![];
-    while let Some(result) = stream.next().await {
-      let (pt,v) = result?;
-      results.push((pt,georender_pack::decode(&v.data)?));
-    }
-    results.sort_by(|a,b| {
-      let a_id = get_id(&a.1);
-      let b_id = get_id(&b.1);
-      fn get_id(x: &georender_pack::Feature) -> u64 {
-        match x {
-          georender_pack::Feature::Point(x) => x.id,
-          georender_pack::Feature::Line(x) => x.id,
-          georender_pack::Feature::Area(x) => x.id,
-        }
-      }
-      match (a_id < b_id, a_id > b_id) {
-        (true,_) => std::cmp::Ordering::Less,
-        (_,true) => std::cmp::Ordering::Greater,
-        (false,false) => std::cmp::Ordering::Equal,
-      }
-    });
+    let results = query(&ingest, &((3.0,-15.0),(15.0,45.0))).await?;
+    // moving a node of the outer way leaves the inner way outside of it,
+    // so the inner way becomes an area of its own
     let ex_positions = vec![
       4.999,  -9.999, 5.000, -10.010, 5.010, -10.010, 5.001, -10.001,
       5.005, -10.003, 5.006, -10.004, 5.007, -10.003,
     ];
-    let ex_cells = earcutr::earcut(&ex_positions.iter()
-      .map(|p| *p as f64).collect(), &vec![4], 2);
+    let mut ex_cells = earcutr::earcut(&ex_positions[..8].iter()
+      .map(|p| *p as f64).collect(), &vec![], 2);
+    ex_cells.extend(earcutr::earcut(&ex_positions[8..].iter()
+      .map(|p| *p as f64).collect(), &vec![], 2).iter().map(|c| c+4));
     assert_eq![
       results,
       vec![
@@ -129,7 +82,7 @@ async fn ingest() -> Result<(),Error> {
           id: 700*3+2,
           feature_type: get_type("natural.water"),
           positions: ex_positions,
-          cells: ex_cells, // vec![0, 4, 6, 1, 2, 3, 1, 3, 0, 5, 4, 0],
+          cells: ex_cells,
           labels: "\x0a=cool lake\x00".as_bytes().to_vec(),
         })),
         ((C::Scalar(13.02),C::Scalar(37.00)), Feature::Point(Point {
@@ -153,41 +106,22 @@ async fn ingest() -> Result<(),Error> {
     o5c_file.push("tests/data/0/changeset1.o5c");
     ingest.changeset(Box::new(File::open(&o5c_file).await?)).await?;
 
-    let mut estore = ingest.estore.lock().await;
-    let mut stream = estore.db.query(&((3.0,-15.0),(15.0,45.0))).await?;
-    let mut results = vec![];
-    while let Some(result) = stream.next().await {
-      let (pt,v) = result?;
-      results.push((pt,georender_pack::decode(&v.data)?));
-    }
-    results.sort_by(|a,b| {
-      let a_id = get_id(&a.1);
-      let b_id = get_id(&b.1);
-      fn get_id(x: &georender_pack::Feature) -> u64 {
-        match x {
-          georender_pack::Feature::Point(x) => x.id,
-          georender_pack::Feature::Line(x) => x.id,
-          georender_pack::Feature::Area(x) => x.id,
-        }
-      }
-      match (a_id < b_id, a_id > b_id) {
-        (true,_) => std::cmp::Ordering::Less,
-        (_,true) => std::cmp::Ordering::Greater,
-        (false,false) => std::cmp::Ordering::Equal,
-      }
-    });
+    let results = query(&ingest, &((3.0,-15.0),(15.0,45.0))).await?;
     let w_ex_positions = vec![
       5.004, -10.006, 5.005, -10.006, 5.005, -10.007, 5.004, -10.007,
     ];
     let w_ex_cells = earcutr::earcut(&w_ex_positions.iter()
       .map(|p| *p as f64).collect(), &vec![], 2);
+    // the island is a hole in the outer way and the old inner way is outside of it
     let r_ex_positions = vec![
       4.999,  -9.999, 5.000, -10.010, 5.010, -10.010, 5.001, -10.001,
-      5.005, -10.003, 5.006, -10.004, 5.007, -10.003,
       5.004, -10.006, 5.005, -10.006, 5.005, -10.007, 5.004, -10.007,
+      5.005, -10.003, 5.006, -10.004, 5.007, -10.003,
     ];
-    let r_ex_cells = earcutr::earcut(&r_ex_positions.iter()
-      .map(|p| *p as f64).collect(), &vec![4,7], 2);
+    let mut r_ex_cells = earcutr::earcut(&r_ex_positions[..16].iter()
+      .map(|p| *p as f64).collect(), &vec![4], 2);
+    r_ex_cells.extend(earcutr::earcut(&r_ex_positions[16..].iter()
+      .map(|p| *p as f64).collect(), &vec![], 2).iter().map(|c| c+8));
     assert_eq![
       results,
       vec![
@@ -226,29 +160,7 @@ async fn ingest() -> Result<(),Error> {
     o5c_file.push("tests/data/0/changeset2.o5c");
     ingest.changeset(Box::new(File::open(&o5c_file).await?)).await?;
 
-    let mut estore = ingest.estore.lock().await;
-    let mut stream = estore.db.query(&((3.0,-15.0),(15.0,45.0))).await?;
-    let mut results = vec![];
-    while let Some(result) = stream.next().await {
-      let (pt,v) = result?;
-      results.push((pt,georender_pack::decode(&v.data)?));
-    }
-    results.sort_by(|a,b| {
-      let a_id = get_id(&a.1);
-      let b_id = get_id(&b.1);
-      fn get_id(x: &georender_pack::Feature) -> u64 {
-        match x {
-          georender_pack::Feature::Point(x) => x.id,
-          georender_pack::Feature::Line(x) => x.id,
-          georender_pack::Feature::Area(x) => x.id,
-        }
-      }
-      match (a_id < b_id, a_id > b_id) {
-        (true,_) => std::cmp::Ordering::Less,
-        (_,true) => std::cmp::Ordering::Greater,
-        (false,false) => std::cmp::Ordering::Equal,
-      }
-    });
+    let results = query(&ingest, &((3.0,-15.0),(15.0,45.0))).await?;
     let w_ex_positions = vec![
       5.004, -10.006, 5.005, -10.006, 5.005, -10.007, 5.003, -10.008,
     ];
@@ -256,11 +168,13 @@ async fn ingest() -> Result<(),Error> {
       .map(|p| *p as f64).collect(), &vec![], 2);
     let r_ex_positions = vec![
       4.999,  -9.999, 5.000, -10.010, 5.010, -10.010, 5.001, -10.001,
-      5.005, -10.003, 5.006, -10.004, 5.007, -10.003,
       5.004, -10.006, 5.005, -10.006, 5.005, -10.007, 5.003, -10.008,
+      5.005, -10.003, 5.006, -10.004, 5.007, -10.003,
     ];
-    let r_ex_cells = earcutr::earcut(&r_ex_positions.iter()
-      .map(|p| *p as f64).collect(), &vec![4,7], 2);
+    let mut r_ex_cells = earcutr::earcut(&r_ex_positions[..16].iter()
+      .map(|p| *p as f64).collect(), &vec![4], 2);
+    r_ex_cells.extend(earcutr::earcut(&r_ex_positions[16..].iter()
+      .map(|p| *p as f64).collect(), &vec![], 2).iter().map(|c| c+8));
     assert_eq![
       results,
       vec![
@@ -305,29 +219,7 @@ async fn ingest() -> Result<(),Error> {
     o5c_file.push("tests/data/0/changeset3.o5c");
     ingest.changeset(Box::new(File::open(&o5c_file).await?)).await?;
 
-    let mut estore = ingest.estore.lock().await;
-    let mut stream = estore.db.query(&((3.0,-15.0),(15.0,45.0))).await?;
-    let mut results = vec![];
-    while let Some(result) = stream.next().await {
-      let (pt,v) = result?;
-      results.push((pt,georender_pack::decode(&v.data)?));
-    }
-    results.sort_by(|a,b| {
-      let a_id = get_id(&a.1);
-      let b_id = get_id(&b.1);
-      fn get_id(x: &georender_pack::Feature) -> u64 {
-        match x {
-          georender_pack::Feature::Point(x) => x.id,
-          georender_pack::Feature::Line(x) => x.id,
-          georender_pack::Feature::Area(x) => x.id,
-        }
-      }
-      match (a_id < b_id, a_id > b_id) {
-        (true,_) => std::cmp::Ordering::Less,
-        (_,true) => std::cmp::Ordering::Greater,
-        (false,false) => std::cmp::Ordering::Equal,
-      }
-    });
+    let results = query(&ingest, &((3.0,-15.0),(15.0,45.0))).await?;
     let w_ex_positions = vec![
       5.004, -10.006, 5.005, -10.006, 5.005, -10.007, 5.003, -10.008,
     ];
@@ -335,17 +227,13 @@ async fn ingest() -> Result<(),Error> {
       .map(|p| *p as f64).collect(), &vec![], 2);
     let r_ex_positions = vec![
       4.999,  -9.999, 5.000, -10.010, 5.010, -10.010, 5.001, -10.001,
-      5.005, -10.003, 5.006, -10.004, 5.007, -10.003,
       5.004, -10.006, 5.005, -10.006, 5.005, -10.007, 5.003, -10.008,
+      5.005, -10.003, 5.006, -10.004, 5.007, -10.003,
     ];
-    let r_ex_cells = earcutr::earcut(&r_ex_positions.iter()
-      .map(|p| *p as f64).collect(), &vec![4,7], 2);
-    let c_ex_positions = vec![
-      7.010, 15.010, 7.000, 15.000, 7.000, 15.020,
-      7.002, 15.002, 7.002, 15.018, 7.008, 15.010,
-    ];
-    let c_ex_cells = earcutr::earcut(&c_ex_positions.iter()
-      .map(|p| *p as f64).collect(), &vec![3], 2);
+    let mut r_ex_cells = earcutr::earcut(&r_ex_positions[..16].iter()
+      .map(|p| *p as f64).collect(), &vec![4], 2);
+    r_ex_cells.extend(earcutr::earcut(&r_ex_positions[16..].iter()
+      .map(|p| *p as f64).collect(), &vec![], 2).iter().map(|c| c+8));
     assert_eq![
       results,
       vec![
@@ -375,19 +263,13 @@ async fn ingest() -> Result<(),Error> {
           point: (13.03,37.04),
           labels: vec![0],
         })),
+        // the outer ways of the castle don't close into a ring, so only its wall has a row
         ((C::Interval(6.998,7.012),C::Interval(14.998,15.022)), Feature::Line(Line {
           id: 4003*3+1,
           feature_type: get_type("historic.castle_wall"),
           positions: vec![ 6.998, 14.998, 7.012, 15.010, 6.998, 15.022 ],
           labels: vec![0],
         })),
-        ((C::Interval(7.000,7.010),C::Interval(15.000,15.020)), Feature::Area(Area {
-          id: 4004*3+2,
-          feature_type: get_type("historic.castle"),
-          positions: c_ex_positions,
-          cells: c_ex_cells,
-          labels: vec![0],
-        })),
         ((C::Scalar(5.003),C::Scalar(-10.008)), Feature::Point(Point {
           id: 9104*3+0,
           feature_type: get_type("amenity.boat_rental"),
@@ -401,6 +283,1159 @@ async fn ingest() -> Result<(),Error> {
   Ok(())
 }
 
+#[async_std::test]
+async fn ingest_xml() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut ingests = vec![];
+  for name in ["pbf","xml"].iter() {
+    ingests.push(setup(dir.path(), name).await?);
+  }
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut xml_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  xml_file.push("tests/data/0/ingest.xml");
+  ingests[0].load_pbf(std::fs::File::open(&pbf_file)?).await?;
+  ingests[1].load_xml(std::io::BufReader::new(std::fs::File::open(&xml_file)?)).await?;
+
+  // metadata records differ by input format, so only compare the id records
+  let mut records = vec![];
+  for ingest in ingests.iter() {
+    let db = ingest.lstore.lock().await.db.clone();
+    let iter = db.iter(ReadOptions::new());
+    records.push(iter.map(|(k,v)| (k.data,v))
+      .filter(|(k,_)| k[0] == peermaps_ingest::ID_PREFIX)
+      .collect::<Vec<_>>());
+  }
+  assert![!records[0].is_empty()];
+  assert_eq![records[0], records[1]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn ingest_threads() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut records = vec![];
+  for threads in [1,4].iter() {
+    let mut ingest = setup(dir.path(), threads).await?.threads(*threads);
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    let db = ingest.lstore.lock().await.db.clone();
+    let iter = db.iter(ReadOptions::new());
+    records.push(iter.map(|(k,v)| (k.data,v))
+      .filter(|(k,_)| k[0] == peermaps_ingest::ID_PREFIX)
+      .collect::<Vec<_>>());
+  }
+  assert![!records[0].is_empty()];
+  assert_eq![records[0], records[1]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn process_threads() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut results = vec![];
+  let mut backrefs = vec![];
+  for threads in [1,4].iter() {
+    let mut ingest = setup(dir.path(), threads).await?.threads(*threads);
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    ingest.process().await;
+
+    let db = ingest.lstore.lock().await.db.clone();
+    let iter = db.iter(ReadOptions::new());
+    backrefs.push(iter.map(|(k,_)| k.data)
+      .filter(|k| k[0] == peermaps_ingest::BACKREF_PREFIX)
+      .collect::<Vec<_>>());
+
+    results.push(query(&ingest, &((3.0,-15.0),(15.0,45.0))).await?);
+  }
+  assert![!results[0].is_empty()];
+  assert_eq![results[0], results[1]];
+  assert![!backrefs[0].is_empty()];
+  assert_eq![backrefs[0], backrefs[1]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn ingest_o5m() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+
+  // changeset3 only creates elements, so it doubles as a complete o5m file
+  let mut o5m_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  o5m_file.push("tests/data/0/changeset3.o5c");
+
+  let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+  let reported = errors.clone();
+  let mut ingest = setup(dir.path(), "ingest").await?.reporter(Box::new(move |_,res| {
+    if let Err(e) = res { reported.lock().unwrap().push(e.to_string()) }
+  }));
+  ingest.load_o5m(Box::new(File::open(&o5m_file).await?)).await?;
+  ingest.process().await;
+  // the outer ways of the castle don't close into a ring
+  assert_eq![*errors.lock().unwrap(), vec![
+    "relation 4004 is not a valid multipolygon: ways do not close into a ring between nodes 3002 and 3003".to_string(),
+  ]];
+
+  let results = query(&ingest, &((3.0,-15.0),(15.0,45.0))).await?;
+  assert_eq![
+    results,
+    vec![
+      ((C::Interval(6.998,7.012),C::Interval(14.998,15.022)), Feature::Line(Line {
+        id: 4003*3+1,
+        feature_type: get_type("historic.castle_wall"),
+        positions: vec![ 6.998, 14.998, 7.012, 15.010, 6.998, 15.022 ],
+        labels: vec![0],
+      })),
+    ]
+  ];
+  Ok(())
+}
+
+#[async_std::test]
+async fn changeset_osc() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut ingests = vec![];
+  for name in ["o5c","osc"].iter() {
+    let mut ingest = setup(dir.path(), name).await?;
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    ingest.process().await;
+    ingests.push(ingest);
+  }
+
+  // the o5c fixtures are generated from the osc fixtures,
+  // so both paths should arrive at the same features
+  for i in 0..4 {
+    let mut o5c_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    o5c_file.push(format!["tests/data/0/changeset{}.o5c",i]);
+    let mut osc_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    osc_file.push(format!["tests/data/0/changeset{}.xml",i]);
+    ingests[0].changeset(Box::new(File::open(&o5c_file).await?)).await?;
+    ingests[1].changeset_osc(std::io::BufReader::new(std::fs::File::open(&osc_file)?)).await?;
+
+    let mut results = vec![];
+    for ingest in ingests.iter() {
+      results.push(query(ingest, &((3.0,-15.0),(15.0,45.0))).await?);
+    }
+    assert![!results[0].is_empty()];
+    assert_eq![results[0], results[1]];
+  }
+  Ok(())
+}
+
+#[async_std::test]
+async fn update_after_delete() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut edb_dir = std::path::PathBuf::from(&dir.path());
+  edb_dir.push("edb");
+  let mut estore = EStore::new(eyros::open_from_path2(&std::path::Path::new(&edb_dir)).await?);
+  let value: peermaps_ingest::V = vec![1,0,21].into();
+  let id = eyros::Value::get_id(&value);
+  let p0 = (C::Interval(1.0,2.0),C::Interval(1.0,2.0));
+  let p1 = (C::Interval(3.0,4.0),C::Interval(3.0,4.0));
+  estore.create(p0.clone(), value.clone()).await?;
+  estore.flush().await?;
+
+  // a changeset can delete and then re-create an element in the same batch
+  estore.push_delete(p0.clone(), id);
+  estore.push_update(&p0, &p1, &value);
+  estore.flush().await?;
+  let mut stream = estore.db.query(&((-180.0,-90.0),(180.0,90.0))).await?;
+  let mut results = vec![];
+  while let Some(result) = stream.next().await {
+    let (pt,v) = result?;
+    results.push((pt,v.data));
+  }
+  assert_eq![results, vec![(p1,vec![1,0,21])]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn decompress() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let data_file = |name: &str| {
+    let mut file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    file.push("tests/data/0");
+    file.push(name);
+    file
+  };
+  let mut ingests = vec![];
+  for name in ["plain","compressed"].iter() {
+    ingests.push(setup(dir.path(), name).await?);
+  }
+
+  ingests[0].load_pbf(std::fs::File::open(data_file("ingest.pbf"))?).await?;
+  ingests[0].process().await;
+  ingests[0].changeset(Box::new(File::open(data_file("changeset0.o5c")).await?)).await?;
+  ingests[0].changeset_osc(std::io::BufReader::new(
+    std::fs::File::open(data_file("changeset1.xml"))?
+  )).await?;
+
+  ingests[1].load_pbf(std::fs::File::open(data_file("ingest.pbf.gz"))?).await?;
+  ingests[1].process().await;
+  ingests[1].changeset(Box::new(File::open(data_file("changeset0.o5c.zst")).await?)).await?;
+  ingests[1].changeset_osc(std::io::BufReader::new(
+    std::fs::File::open(data_file("changeset1.xml.bz2"))?
+  )).await?;
+
+  let mut results = vec![];
+  for ingest in ingests.iter() {
+    results.push(query(ingest, &((3.0,-15.0),(15.0,45.0))).await?);
+  }
+  assert![!results[0].is_empty()];
+  assert_eq![results[0], results[1]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn replicate() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let data_file = |name: &str| {
+    let mut file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    file.push("tests/data/0");
+    file.push(name);
+    file
+  };
+  let mut ingests = vec![];
+  for name in ["changeset","replicate"].iter() {
+    let mut ingest = setup(dir.path(), name).await?;
+    ingest.load_pbf(std::fs::File::open(data_file("ingest.pbf"))?).await?;
+    ingest.process().await;
+    ingests.push(ingest);
+  }
+
+  ingests[0].changeset(Box::new(File::open(data_file("changeset0.o5c")).await?)).await?;
+  ingests[0].changeset(Box::new(File::open(data_file("changeset1.o5c")).await?)).await?;
+
+  // without a stored or explicit sequence there is nothing to start from
+  assert![ingests[1].replicate(&data_file("replication"), None).await.is_err()];
+  assert_eq![ingests[1].replicate(&data_file("replication"), Some(0)).await?, 2];
+  assert_eq![
+    ingests[1].get_meta("replication_sequence").await?,
+    Some(vec![2])
+  ];
+  assert_eq![
+    ingests[1].get_meta("replication_timestamp").await?,
+    Some(b"2021-03-06T12:01:00Z".to_vec())
+  ];
+  // running again without a sequence picks up from the stored one
+  assert_eq![ingests[1].replicate(&data_file("replication"), None).await?, 2];
+
+  let mut results = vec![];
+  for ingest in ingests.iter() {
+    results.push(query(ingest, &((3.0,-15.0),(15.0,45.0))).await?);
+  }
+  assert![!results[0].is_empty()];
+  assert_eq![results[0], results[1]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn metadata() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut o5c_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  o5c_file.push("tests/data/0/changeset0.o5c");
+
+  let mut ingest = setup(dir.path(), "ingest").await?;
+  ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+  ingest.process().await;
+  ingest.changeset(Box::new(File::open(&o5c_file).await?)).await?;
+
+  let records = {
+    let mut lstore = ingest.lstore.lock().await;
+    peermaps_ingest::meta::list(&mut lstore)?
+  };
+  let names = records.iter().map(|(name,_)| name.as_str()).collect::<Vec<_>>();
+  assert_eq![names, vec![
+    "changeset_time", "format", "pbf_time", "process_time", "schema_version",
+    "source", "version", "writing_program",
+  ]];
+  assert_eq![
+    ingest.get_meta("format").await?,
+    Some(b"pbf".to_vec())
+  ];
+  assert_eq![
+    ingest.get_meta("schema_version").await?,
+    Some(vec![peermaps_ingest::meta::SCHEMA_VERSION as u8])
+  ];
+  assert_eq![
+    ingest.get_meta("writing_program").await?,
+    Some(b"osmconvert 0.8.10".to_vec())
+  ];
+  assert_eq![
+    ingest.get_meta("version").await?,
+    Some(env!("CARGO_PKG_VERSION").as_bytes().to_vec())
+  ];
+  assert_eq![peermaps_ingest::meta::iso_time(1614816000), "2021-03-04T00:00:00Z"];
+
+  // lengths from a file that isn't a pbf are checked before allocating
+  let res = peermaps_ingest::meta::PbfHeader::read(&mut std::io::Cursor::new(vec![0xff;16]));
+  assert_eq![
+    res.unwrap_err().to_string(),
+    "invalid pbf header: blob header of 4294967295 bytes is too large"
+  ];
+  Ok(())
+}
+
+#[async_std::test]
+async fn schema_version() -> Result<(),Error> {
+  use peermaps_ingest::{meta,migrate};
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+
+  let mut ingest = setup(dir.path(), "ingest").await?;
+  // a new database gets the current version
+  assert_eq![migrate::schema_version(&mut *ingest.lstore.lock().await)?, None];
+  ingest.check_schema().await?;
+  assert_eq![
+    migrate::schema_version(&mut *ingest.lstore.lock().await)?,
+    Some(meta::SCHEMA_VERSION)
+  ];
+  ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+  ingest.check_schema().await?;
+  assert_eq![
+    migrate::migrate(&mut *ingest.lstore.lock().await, meta::SCHEMA_VERSION, || {})?,
+    meta::SCHEMA_VERSION
+  ];
+
+  // databases from a newer version are refused and can't be migrated
+  let newer = meta::LATEST_SCHEMA_VERSION+1;
+  ingest.put_meta("schema_version", &meta::encode_u64(newer)?).await?;
+  assert![ingest.check_schema().await.is_err()];
+  assert![
+    migrate::migrate(&mut *ingest.lstore.lock().await, meta::LATEST_SCHEMA_VERSION, || {}).is_err()
+  ];
+  Ok(())
+}
+
+#[async_std::test]
+async fn verify() -> Result<(),Error> {
+  use peermaps_ingest::verify::Problem;
+  use desert::varint;
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+
+  let mut ingest = setup(dir.path(), "ingest").await?;
+  ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+  ingest.process().await;
+  assert_eq![ingest.verify(false).await?, vec![]];
+
+  fn backref_key(a: u64, b: u64) -> Result<Key,Error> {
+    let mut key = vec![0u8;1+varint::length(a)+varint::length(b)];
+    key[0] = peermaps_ingest::BACKREF_PREFIX;
+    let s = varint::encode(a, &mut key[1..])?;
+    varint::encode(b, &mut key[1+s..])?;
+    Ok(Key::from(&key))
+  }
+  {
+    let mut lstore = ingest.lstore.lock().await;
+    lstore.del(backref_key(1313*3+0, 555*3+1)?)?;
+    lstore.put(backref_key(1312*3+0, 555*3+1)?, &vec![])?;
+    lstore.flush()?;
+  }
+  {
+    let mut estore = ingest.estore.lock().await;
+    estore.delete((C::Scalar(13.02),C::Scalar(37.0)), 1312*3+0).await?;
+    estore.flush().await?;
+  }
+  let problems = ingest.verify(false).await?;
+  assert_eq![problems.len(), 3];
+  assert![problems.contains(&Problem::MissingRow { ex_id: 1312*3+0 })];
+  assert![problems.contains(&Problem::MissingBackref { ex_id: 1313*3+0, ref_id: 555*3+1 })];
+  assert![problems.contains(&Problem::DanglingBackref { ex_id: 1312*3+0, ref_id: 555*3+1 })];
+
+  assert_eq![ingest.verify(true).await?.len(), 3];
+  assert_eq![ingest.verify(false).await?, vec![]];
+
+  // rows written more than once, in separate batches so they land in separate trees
+  async fn rows_of(ingest: &Ingest, ex_id: u64) -> Result<Vec<((C<f32>,C<f32>),Vec<u8>)>,Error> {
+    let mut estore = ingest.estore.lock().await;
+    let mut stream = estore.db.query(&((-180.0,-90.0),(180.0,90.0))).await?;
+    let mut results = vec![];
+    while let Some(result) = stream.next().await {
+      let (pt,v) = result?;
+      if eyros::Value::get_id(&v) == ex_id { results.push((pt,v.data)) }
+    }
+    Ok(results)
+  }
+  let node_rows = rows_of(&ingest, 1312*3+0).await?;
+  assert_eq![node_rows.len(), 1];
+  {
+    let mut estore = ingest.estore.lock().await;
+    for _ in 0..3 {
+      estore.create(node_rows[0].0.clone(), node_rows[0].1.clone().into()).await?;
+      estore.flush().await?;
+      // node 7 has no record
+      estore.create((C::Scalar(1.0),C::Scalar(2.0)), vec![1,0,21].into()).await?;
+      estore.flush().await?;
+    }
+  }
+  assert_eq![ingest.verify(false).await?, vec![
+    Problem::DuplicateRow { ex_id: 1312*3+0, count: 4 },
+    Problem::OrphanRow { ex_id: 7*3+0 },
+  ]];
+  assert_eq![ingest.verify(true).await?.len(), 2];
+  assert_eq![ingest.verify(false).await?, vec![]];
+  assert_eq![rows_of(&ingest, 1312*3+0).await?, node_rows];
+  assert_eq![rows_of(&ingest, 7*3+0).await?, vec![]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn rebuild_backrefs() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+
+  let mut ingest = setup(dir.path(), "ingest").await?;
+  ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+  ingest.process().await;
+
+  let backref_keys = |db: &Database<Key>| {
+    let gt = Key::from(&[peermaps_ingest::BACKREF_PREFIX]);
+    let lt = Key::from(&[peermaps_ingest::BACKREF_PREFIX+1]);
+    db.keys_iter(ReadOptions::new())
+      .from(&gt).to(&lt)
+      .collect::<Vec<Key>>()
+  };
+  let expected = backref_keys(&ingest.lstore.lock().await.db);
+  assert![!expected.is_empty()];
+  {
+    let mut lstore = ingest.lstore.lock().await;
+    for key in expected.iter().step_by(2) {
+      lstore.del(key.clone())?;
+    }
+    lstore.put(Key::from(&[peermaps_ingest::BACKREF_PREFIX,3,4]), &vec![])?;
+    lstore.flush()?;
+  }
+  assert_ne![backref_keys(&ingest.lstore.lock().await.db), expected];
+  ingest.rebuild_backrefs().await?;
+  assert_eq![backref_keys(&ingest.lstore.lock().await.db), expected];
+  assert_eq![ingest.verify(false).await?, vec![]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn node_cache() -> Result<(),Error> {
+  use peermaps_ingest::{NodeCache,Coords};
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut nodes_file = std::path::PathBuf::from(&dir.path());
+  nodes_file.push("nodes");
+  let mut ingests = vec![];
+  for name in ["ldb","cache"].iter() {
+    let mut ingest = setup(dir.path(), name).await?;
+    if name == &"cache" {
+      ingest = ingest.node_cache(NodeCache::open(&nodes_file)?);
+    }
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    ingest.process().await;
+    ingests.push(ingest);
+  }
+  {
+    let nodes = NodeCache::open(&nodes_file)?;
+    assert_eq![nodes.get(1312,Coords::Fixed()), Some((13.02,37.0))];
+    assert_eq![nodes.get(1311,Coords::Fixed()), None];
+    assert_eq![nodes.get(1<<40,Coords::Fixed()), None];
+  }
+
+  for i in 0..4 {
+    let mut o5c_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    o5c_file.push(format!["tests/data/0/changeset{}.o5c",i]);
+    for ingest in ingests.iter_mut() {
+      ingest.changeset(Box::new(File::open(&o5c_file).await?)).await?;
+    }
+    let mut results = vec![];
+    for ingest in ingests.iter() {
+      results.push(query(ingest, &((3.0,-15.0),(15.0,45.0))).await?);
+    }
+    assert![!results[0].is_empty()];
+    assert_eq![results[0], results[1]];
+  }
+  Ok(())
+}
+
+#[async_std::test]
+async fn float_node_cache() -> Result<(),Error> {
+  use peermaps_ingest::NodeCache;
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let nodes_file = dir.path().join("nodes");
+  // f32 is finer than 1e-7 degrees near 0, so these don't survive a round trip
+  // through fixed-point
+  let osc = r#"<osmChange version="0.6"><create>
+    <node id="4000" lon="0.12345678" lat="-0.87654321"/>
+    <node id="4001" lon="0.32345671" lat="-0.67654329"/>
+    <way id="4002"><nd ref="4000"/><nd ref="4001"/><tag k="highway" v="residential"/></way>
+  </create></osmChange>"#;
+  let mut results = vec![];
+  for name in ["ldb","cache"].iter() {
+    let mut ingest = setup(dir.path(), name).await?.schema_version(1);
+    if name == &"cache" {
+      ingest = ingest.node_cache(NodeCache::open(&nodes_file)?);
+    }
+    ingest.check_schema().await?;
+    ingest.changeset_osc(osc.as_bytes()).await?;
+    results.push(query(&ingest, &((-1.0,-1.0),(1.0,1.0))).await?);
+  }
+  assert![!results[0].is_empty()];
+  assert_eq![results[0], results[1]];
+
+  // the rows written with the cache match the records without it
+  let mut ingest = setup(dir.path(), "cache").await?.schema_version(1);
+  ingest.check_schema().await?;
+  assert_eq![ingest.verify(false).await?, vec![]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn fixed_coords() -> Result<(),Error> {
+  use peermaps_ingest::{meta,meta_key,migrate,Coords,Decoded,decode};
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut ingests = vec![];
+  for version in [1,2].iter() {
+    let mut ingest = setup(dir.path(), version).await?.schema_version(*version);
+    ingest.check_schema().await?;
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    ingest.process().await;
+    ingests.push(ingest);
+  }
+  assert_eq![ingests[1].coords(), Coords::Fixed()];
+  assert_eq![
+    ingests[1].get_meta("schema_version").await?,
+    Some(meta::encode_u64(2)?)
+  ];
+
+  // both layouts render the same features
+  let mut results = vec![];
+  for ingest in ingests.iter() {
+    results.push(query(ingest, &((3.0,-15.0),(15.0,45.0))).await?);
+  }
+  assert![!results[0].is_empty()];
+  assert_eq![results[0], results[1]];
+
+  // a version 1 database can't be opened as version 2 until it is migrated
+  let mut lstore = ingests[0].lstore.lock().await;
+  assert![migrate::check(&mut lstore, Some(2)).is_err()];
+  // a migration that didn't finish leaves a marker and the database is refused
+  lstore.put(Key::from(&meta_key("migrating")), &meta::encode_u64(2)?)?;
+  assert![migrate::check(&mut lstore, None).is_err()];
+  assert![migrate::migrate(&mut lstore, 2, || {}).is_err()];
+  lstore.del(Key::from(&meta_key("migrating")))?;
+  assert_eq![migrate::migrate(&mut lstore, 2, || {})?, 2];
+  assert_eq![migrate::check(&mut lstore, Some(2))?, 2];
+  assert_eq![lstore.get(&Key::from(&meta_key("migrating")))?, None];
+  let mut records = vec![];
+  for db in [lstore.db.clone(), ingests[1].lstore.lock().await.db.clone()].iter() {
+    let iter = db.iter(ReadOptions::new());
+    records.push(iter.filter(|(k,_)| k.data[0] == peermaps_ingest::ID_PREFIX)
+      .map(|(k,v)| decode(&k.data,&v,Coords::Fixed()))
+      .collect::<Result<Vec<_>,_>>()?);
+  }
+  assert_eq![records[0].len(), records[1].len()];
+  for (a,b) in records[0].iter().zip(records[1].iter()) {
+    match (a,b) {
+      // migrated locations keep the precision of the f32 they were stored as
+      (Decoded::Node(a),Decoded::Node(b)) => {
+        assert_eq![a.id, b.id];
+        assert![(a.lon-b.lon).abs() < 1e-5 && (a.lat-b.lat).abs() < 1e-5];
+      },
+      (a,b) => assert_eq![a,b],
+    }
+  }
+  Ok(())
+}
+
+#[async_std::test]
+async fn raw_tags() -> Result<(),Error> {
+  use peermaps_ingest::{Coords,Decoded,decode,decode_tags,encode_record,id_key,tags_key};
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let node_key = Key::from(&id_key(1312*3+0)?);
+  let mut ingests = vec![];
+  for raw_tags in [true,false].iter() {
+    let mut ingest = setup(dir.path(), raw_tags).await?.raw_tags(*raw_tags);
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    {
+      // pretend the cafe was loaded with a tag mapping that didn't know about cafes
+      let mut lstore = ingest.lstore.lock().await;
+      let buf = lstore.get(&node_key)?.unwrap();
+      if let Decoded::Node(mut node) = decode(&node_key.data, &buf, Coords::Float())? {
+        node.feature_type = get_type("place.other");
+        node.labels = vec![0];
+        lstore.put(node_key.clone(), &encode_record(&Decoded::Node(node), Coords::Float())?)?;
+      }
+    }
+    ingest.process().await;
+    ingests.push(ingest);
+  }
+
+  let key = Key::from(&tags_key(1312*3+0)?);
+  assert_eq![
+    ingests[0].lstore.lock().await.get(&key)?.map(|buf| decode_tags(&buf)).transpose()?,
+    Some(vec![("amenity".to_string(),"cafe".to_string())])
+  ];
+  assert_eq![ingests[1].lstore.lock().await.get(&key)?, None];
+
+  let mut points = vec![];
+  for ingest in ingests.iter() {
+    let mut estore = ingest.estore.lock().await;
+    let mut stream = estore.db.query(&((3.0,-15.0),(15.0,45.0))).await?;
+    let mut ids = vec![];
+    while let Some(result) = stream.next().await {
+      let (_,v) = result?;
+      if let Feature::Point(p) = georender_pack::decode(&v.data)? {
+        ids.push((p.id,p.feature_type));
+      }
+    }
+    ids.sort();
+    points.push(ids);
+  }
+  // process derives the feature type from the raw tags and writes it back
+  assert![points[0].contains(&(1312*3+0,get_type("amenity.cafe")))];
+  assert![!points[1].iter().any(|(id,_)| *id == 1312*3+0)];
+  let buf = ingests[0].lstore.lock().await.get(&node_key)?.unwrap();
+  match decode(&node_key.data, &buf, Coords::Float())? {
+    Decoded::Node(node) => assert_eq![node.feature_type, get_type("amenity.cafe")],
+    _ => panic!["expected a node"],
+  }
+
+  // verify retags records like process does, so the row still matches when the
+  // stored record has the stale feature type again
+  if let Decoded::Node(mut node) = decode(&node_key.data, &buf, Coords::Float())? {
+    node.feature_type = get_type("place.other");
+    node.labels = vec![0];
+    let value = encode_record(&Decoded::Node(node), Coords::Float())?;
+    ingests[0].lstore.lock().await.put(node_key.clone(), &value)?;
+  }
+  assert_eq![ingests[0].verify(false).await?, vec![]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn element_info() -> Result<(),Error> {
+  use peermaps_ingest::{Coords,Decoded,ElementInfo,decode,decode_info,id_key,info_key};
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  fn modify(version: u64, lon: f64) -> String {
+    format![r#"<osmChange version="0.6"><modify>
+      <node id="2000" version="{}" timestamp="2021-03-0{}T12:00:00Z" lon="{}" lat="37.04"/>
+    </modify></osmChange>"#, version, version, lon]
+  }
+  let changes = vec![
+    modify(2, 13.03),
+    modify(2, 13.04), // same version again
+    modify(1, 13.05), // older version
+    r#"<osmChange version="0.6"><delete>
+      <node id="2000" version="3" timestamp="2021-03-03T12:00:00Z"/>
+    </delete></osmChange>"#.to_string(),
+    modify(2, 13.06), // older than the delete
+  ];
+  let node_key = Key::from(&id_key(2000*3+0)?);
+  let mut lons = vec![];
+  for element_info in [true,false].iter() {
+    let mut ingest = setup(dir.path(), element_info).await?.element_info(*element_info);
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    ingest.process().await;
+    let mut ilons = vec![];
+    for change in changes.iter() {
+      ingest.changeset_osc(change.as_bytes()).await?;
+      let buf = ingest.lstore.lock().await.get(&node_key)?;
+      ilons.push(match buf.map(|b| decode(&node_key.data, &b, Coords::Float())).transpose()? {
+        Some(Decoded::Node(node)) => Some((node.lon*100.0).round()/100.0),
+        _ => None,
+      });
+    }
+    if *element_info {
+      let buf = ingest.lstore.lock().await.get(&Key::from(&info_key(2000*3+0)?))?.unwrap();
+      assert_eq![decode_info(&buf)?, ElementInfo {
+        version: 3,
+        timestamp: 1614772800,
+        changeset: 0,
+        uid: 0,
+      }];
+    }
+    lons.push(ilons);
+  }
+  assert_eq![lons[0], vec![Some(13.03),Some(13.03),Some(13.03),None,None]];
+  assert_eq![lons[1], vec![Some(13.03),Some(13.04),Some(13.05),None,Some(13.06)]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn bbox() -> Result<(),Error> {
+  use peermaps_ingest::{id_key,extract::parse_bbox};
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+
+  assert![parse_bbox("13.0,37.0,12.0,38.0").is_err()];
+  assert![parse_bbox("13.0,37.0,14.0").is_err()];
+  // only the cafe and one corner of the park are inside
+  let mut ingest = setup(dir.path(), "ingest").await?.bbox(parse_bbox("13.005,36.99,13.025,37.005")?);
+  ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+  ingest.process().await;
+  ingest.changeset_osc(r#"<osmChange version="0.6"><create>
+    <node id="3000" lon="5.0" lat="-10.0"><tag k="amenity" v="cafe"/></node>
+    <node id="3001" lon="13.01" lat="37.0"><tag k="amenity" v="cafe"/></node>
+  </create></osmChange>"#.as_bytes()).await?;
+
+  let mut stored = vec![];
+  for ex_id in [1312*3+0, 1313*3+0, 1314*3+0, 1315*3+0, 2000*3+0, 3000*3+0, 3001*3+0,
+    9000*3+0, 555*3+1, 600*3+1, 700*3+2].iter() {
+    if ingest.lstore.lock().await.get(&Key::from(&id_key(*ex_id)?))?.is_some() {
+      stored.push(*ex_id);
+    }
+  }
+  // the park keeps its nodes outside of the bbox
+  assert_eq![stored, vec![1312*3+0, 1313*3+0, 1314*3+0, 1315*3+0, 3001*3+0, 555*3+1]];
+
+  let mut estore = ingest.estore.lock().await;
+  let mut stream = estore.db.query(&((-180.0,-90.0),(180.0,90.0))).await?;
+  let mut ids = vec![];
+  while let Some(result) = stream.next().await {
+    ids.push(eyros::Value::get_id(&result?.1));
+  }
+  ids.sort();
+  assert_eq![ids, vec![555*3+1, 1312*3+0, 3001*3+0]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn polygon() -> Result<(),Error> {
+  use peermaps_ingest::{id_key,extract::{Region,parse_poly}};
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+
+  // a hole only cuts into the outer section before it, so an island inside
+  // of the hole is part of the region
+  let region = parse_poly("holes\nouter\n0 0\n2 0\n2 2\n0 2\nEND\n!hole\n0.5 0.5\n1.5 0.5\n1.5 1.5\n0.5 1.5\nEND\n\
+    island\n0.75 0.75\n1.25 0.75\n1.25 1.25\n0.75 1.25\nEND\nEND\n")?;
+  assert![region.contains(0.25,0.25)];
+  assert![!region.contains(0.6,0.6)];
+  assert![region.contains(1.0,1.0)];
+  assert![parse_poly("holes\n!hole\n0 0\n1 0\n1 1\nEND\nEND\n").is_err()];
+
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut results = vec![];
+  for name in ["extract.poly","extract.geojson"].iter() {
+    let mut region_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    region_file.push(format!["tests/data/0/{}",name]);
+    // a diamond around the cafe and a square around the lake's island with a
+    // hole cut out of it
+    let region = Region::from_path(&region_file)?;
+    assert![region.contains(13.02,37.00)];
+    assert![region.contains(5.005,-10.003)];
+    assert![!region.contains(5.006,-10.004)];
+    assert![!region.contains(13.00,37.00)];
+    let mut ingest = setup(dir.path(), name).await?.region(region);
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    ingest.process().await;
+    // a node outside of the region that completes the park becomes a point
+    ingest.changeset_osc(r#"<osmChange version="0.6"><modify>
+      <node id="1313" lon="13.00" lat="37.00"><tag k="amenity" v="cafe"/></node>
+    </modify></osmChange>"#.as_bytes()).await?;
+
+    let mut stored = vec![];
+    for ex_id in [1312*3+0, 1313*3+0, 1314*3+0, 2000*3+0, 9000*3+0, 9004*3+0, 9005*3+0,
+      555*3+1, 600*3+1, 601*3+1, 700*3+2].iter() {
+      if ingest.lstore.lock().await.get(&Key::from(&id_key(*ex_id)?))?.is_some() {
+        stored.push(*ex_id);
+      }
+    }
+    let mut estore = ingest.estore.lock().await;
+    let mut stream = estore.db.query(&((-180.0,-90.0),(180.0,90.0))).await?;
+    let mut ids = vec![];
+    while let Some(result) = stream.next().await {
+      ids.push(eyros::Value::get_id(&result?.1));
+    }
+    ids.sort();
+    results.push((stored,ids));
+  }
+  assert_eq![results[0], results[1]];
+  // the lake is kept for its island and keeps its outer way outside of the
+  // region so that the multipolygon stays complete
+  assert_eq![results[0].0, vec![1312*3+0, 1313*3+0, 1314*3+0, 9000*3+0, 9004*3+0, 9005*3+0,
+    555*3+1, 600*3+1, 601*3+1, 700*3+2]];
+  assert![results[0].1.contains(&(1312*3+0))];
+  assert![!results[0].1.contains(&(1313*3+0))];
+  Ok(())
+}
+
+#[async_std::test]
+async fn tag_filter() -> Result<(),Error> {
+  use peermaps_ingest::filter::Filter;
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut pbf_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  pbf_file.push("tests/data/0/ingest.pbf");
+  let mut results = vec![];
+  for (i,(raw_tags,load_filter)) in [(false,"w/leisure"),(true,"n/amenity")].iter().enumerate() {
+    let mut ingest = setup(dir.path(), i).await?.raw_tags(*raw_tags).filter(Filter::parse(load_filter)?);
+    ingest.load_pbf(std::fs::File::open(&pbf_file)?).await?;
+    if *raw_tags {
+      // process applies its own filter to the raw tags
+      let mut filter = Filter::new();
+      filter.push("leisure")?;
+      ingest = ingest.filter(filter);
+    }
+    ingest.process().await;
+    let mut estore = ingest.estore.lock().await;
+    let mut stream = estore.db.query(&((-180.0,-90.0),(180.0,90.0))).await?;
+    let mut ids = vec![];
+    while let Some(result) = stream.next().await {
+      ids.push(eyros::Value::get_id(&result?.1));
+    }
+    ids.sort();
+    results.push(ids);
+  }
+  // only the park. the lake and the points are filtered out
+  assert_eq![results[0], vec![555*3+1]];
+  assert_eq![results[1], vec![555*3+1]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn relation_members() -> Result<(),Error> {
+  use desert::varint;
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut xml_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  xml_file.push("tests/data/0/nested.xml");
+
+  let mut ingest = setup(dir.path(), "ingest").await?;
+  ingest.load_xml(std::io::BufReader::new(std::fs::File::open(&xml_file)?)).await?;
+  ingest.process().await;
+
+  // the outer relation of the lake supplies its geometry
+  let ex_positions = vec![
+    5.000, -10.000, 5.000, -10.010, 5.010, -10.010, 5.010, -10.000,
+    5.005, -10.003, 5.006, -10.004, 5.007, -10.003,
+  ];
+  // the untagged inner relation and the boundary without ways get no rows
+  assert_eq![query(&ingest, &((-180.0,-90.0),(180.0,90.0))).await?, vec![
+    ((C::Interval(5.000,5.010),C::Interval(-10.010,-10.000)), Feature::Area(Area {
+      id: 720*3+2,
+      feature_type: get_type("natural.water"),
+      positions: ex_positions.clone(),
+      cells: earcutr::earcut(&ex_positions.iter().map(|p| *p as f64).collect(), &vec![4], 2),
+      labels: "\x09=big lake\x00".as_bytes().to_vec(),
+    })),
+  ]];
+
+  fn backref_key(a: u64, b: u64) -> Result<Key,Error> {
+    let mut key = vec![0u8;1+varint::length(a)+varint::length(b)];
+    key[0] = peermaps_ingest::BACKREF_PREFIX;
+    let s = varint::encode(a, &mut key[1..])?;
+    varint::encode(b, &mut key[1+s..])?;
+    Ok(Key::from(&key))
+  }
+  {
+    let mut lstore = ingest.lstore.lock().await;
+    for (a,b) in [(9500*3+0,720*3+2),(710*3+2,720*3+2),(720*3+2,730*3+2),(600*3+1,710*3+2)].iter() {
+      assert![lstore.get(&backref_key(*a,*b)?)?.is_some()];
+    }
+  }
+  assert_eq![ingest.verify(false).await?, vec![]];
+
+  // dropping the hole from the inner relation reaches the lake through its backref
+  ingest.changeset_osc(std::io::Cursor::new(r#"<osmChange version="0.6">
+    <modify>
+      <relation id="710">
+        <member type="way" role="outer" ref="600" />
+        <tag k="type" v="multipolygon" />
+      </relation>
+    </modify>
+  </osmChange>"#.as_bytes())).await?;
+  let ex_positions = vec![ 5.000, -10.000, 5.000, -10.010, 5.010, -10.010, 5.010, -10.000 ];
+  assert_eq![query(&ingest, &((-180.0,-90.0),(180.0,90.0))).await?, vec![
+    ((C::Interval(5.000,5.010),C::Interval(-10.010,-10.000)), Feature::Area(Area {
+      id: 720*3+2,
+      feature_type: get_type("natural.water"),
+      positions: ex_positions.clone(),
+      cells: earcutr::earcut(&ex_positions.iter().map(|p| *p as f64).collect(), &vec![], 2),
+      labels: "\x09=big lake\x00".as_bytes().to_vec(),
+    })),
+  ]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn route() -> Result<(),Error> {
+  use desert::varint;
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut xml_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  xml_file.push("tests/data/0/route.xml");
+
+  let mut ingest = setup(dir.path(), "ingest").await?;
+  ingest.load_xml(std::io::BufReader::new(std::fs::File::open(&xml_file)?)).await?;
+  ingest.process().await;
+
+  // the second way runs backwards, so it is reversed to continue the line
+  assert_eq![query(&ingest, &((-180.0,-90.0),(180.0,90.0))).await?, vec![
+    ((C::Interval(6.000,6.006),C::Interval(-11.006,-11.000)), Feature::Line(Line {
+      id: 810*3+2,
+      feature_type: get_type("route.bus"),
+      positions: vec![
+        6.000, -11.000, 6.001, -11.002, 6.003, -11.003, 6.004, -11.005, 6.006, -11.006,
+      ],
+      labels: "\x03=10\x00".as_bytes().to_vec(),
+    })),
+  ]];
+
+  fn backref_key(a: u64, b: u64) -> Result<Key,Error> {
+    let mut key = vec![0u8;1+varint::length(a)+varint::length(b)];
+    key[0] = peermaps_ingest::BACKREF_PREFIX;
+    let s = varint::encode(a, &mut key[1..])?;
+    varint::encode(b, &mut key[1+s..])?;
+    Ok(Key::from(&key))
+  }
+  {
+    let mut lstore = ingest.lstore.lock().await;
+    for (a,b) in [(800*3+1,810*3+2),(801*3+1,810*3+2),(9100*3+0,810*3+2)].iter() {
+      assert![lstore.get(&backref_key(*a,*b)?)?.is_some()];
+    }
+  }
+  assert_eq![ingest.verify(false).await?, vec![]];
+
+  // editing a member way reaches the route through its backref
+  ingest.changeset_osc(std::io::Cursor::new(r#"<osmChange version="0.6">
+    <create>
+      <node id="9105" lon="6.005" lat="-11.004" />
+    </create>
+    <modify>
+      <way id="801">
+        <nd ref="9104" />
+        <nd ref="9105" />
+        <nd ref="9102" />
+      </way>
+    </modify>
+  </osmChange>"#.as_bytes())).await?;
+  assert_eq![query(&ingest, &((-180.0,-90.0),(180.0,90.0))).await?, vec![
+    ((C::Interval(6.000,6.006),C::Interval(-11.006,-11.000)), Feature::Line(Line {
+      id: 810*3+2,
+      feature_type: get_type("route.bus"),
+      positions: vec![
+        6.000, -11.000, 6.001, -11.002, 6.003, -11.003, 6.005, -11.004, 6.006, -11.006,
+      ],
+      labels: "\x03=10\x00".as_bytes().to_vec(),
+    })),
+  ]];
+  Ok(())
+}
+
+#[test]
+fn multipolygon() -> Result<(),Error> {
+  use peermaps_ingest::multipolygon::{assemble,Ring};
+  let nodes: std::collections::HashMap<u64,(f32,f32)> = vec![
+    (1,(0.0,0.0)), (2,(10.0,0.0)), (3,(10.0,10.0)), (4,(0.0,10.0)),
+    (5,(2.0,2.0)), (6,(8.0,2.0)), (7,(8.0,8.0)), (8,(2.0,8.0)),
+    (21,(4.0,4.0)), (22,(6.0,4.0)), (23,(6.0,6.0)), (24,(4.0,6.0)),
+  ].into_iter().collect();
+  let ways: std::collections::HashMap<u64,Vec<u64>> = vec![
+    (10,vec![1,2,3]), (11,vec![1,4,3]),
+    (12,vec![5,6,7,8,5]), (13,vec![21,22,23,24,21]),
+  ].into_iter().collect();
+  // the outer is split across two ways that run the same direction, the hole
+  // has an outer role, and the island inside of the hole has an inner role
+  assert_eq![assemble(5, &[10*2, 11*2, 12*2, 13*2+1], &ways, &nodes)?, vec![
+    Ring { refs: vec![21,22,23,24,21], inner: false },
+    Ring { refs: vec![1,2,3,4,1], inner: false },
+    Ring { refs: vec![5,6,7,8,5], inner: true },
+  ]];
+  assert_eq![
+    assemble(5, &[10*2], &ways, &nodes).unwrap_err().to_string(),
+    "relation 5 is not a valid multipolygon: ways do not close into a ring between nodes 3 and 1"
+  ];
+  Ok(())
+}
+
+#[async_std::test]
+async fn coastline() -> Result<(),Error> {
+  use peermaps_ingest::{coastline,extract::parse_bbox};
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut xml_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  xml_file.push("tests/data/0/coastline.xml");
+
+  let mut ingest = setup(dir.path(), "ingest").await?.bbox(parse_bbox("10.0,50.0,11.0,51.0")?);
+  ingest.load_xml(std::io::BufReader::new(std::fs::File::open(&xml_file)?)).await?;
+  ingest.process().await;
+  ingest.coastline().await?;
+
+  async fn rows(ingest: &Ingest) -> Result<Vec<((C<f32>,C<f32>),Feature)>,Error> {
+    let mut estore = ingest.estore.lock().await;
+    let mut stream = estore.db.query(&((-180.0,-90.0),(180.0,90.0))).await?;
+    let mut results = vec![];
+    while let Some(result) = stream.next().await {
+      let (pt,v) = result?;
+      if coastline::is_coastline_id(eyros::Value::get_id(&v)) {
+        results.push((eyros::Value::get_id(&v),(pt,georender_pack::decode(&v.data)?)));
+      }
+    }
+    results.sort_by_key(|(id,_)| *id);
+    Ok(results.into_iter().map(|(_,row)| row).collect())
+  }
+  // the coastline runs west to east through the bbox with land to the north,
+  // and the island is a hole in the water to the south
+  let island_positions = vec![10.250, 50.125, 10.500, 50.125, 10.250, 50.250];
+  let land_positions = vec![10.0, 50.5, 10.5, 50.25, 11.0, 50.5, 11.0, 51.0, 10.0, 51.0];
+  let water_positions = vec![
+    11.0, 50.5, 10.5, 50.25, 10.0, 50.5, 10.0, 50.0, 11.0, 50.0,
+    10.250, 50.125, 10.500, 50.125, 10.250, 50.250,
+  ];
+  let cells = |positions: &Vec<f32>, holes: Vec<usize>| {
+    earcutr::earcut(&positions.iter().map(|p| *p as f64).collect(), &holes, 2)
+  };
+  let ex_rows = vec![
+    ((C::Interval(10.25,10.5),C::Interval(50.125,50.25)), Feature::Area(Area {
+      id: coastline::FIRST_ID*3+2,
+      feature_type: get_type("place.island"),
+      positions: island_positions.clone(),
+      cells: cells(&island_positions, vec![]),
+      labels: vec![0],
+    })),
+    ((C::Interval(10.0,11.0),C::Interval(50.25,51.0)), Feature::Area(Area {
+      id: (coastline::FIRST_ID+1)*3+2,
+      feature_type: get_type("place.island"),
+      positions: land_positions.clone(),
+      cells: cells(&land_positions, vec![]),
+      labels: vec![0],
+    })),
+    ((C::Interval(10.0,11.0),C::Interval(50.0,50.5)), Feature::Area(Area {
+      id: (coastline::FIRST_ID+2)*3+2,
+      feature_type: get_type("place.ocean"),
+      positions: water_positions.clone(),
+      cells: cells(&water_positions, vec![5]),
+      labels: vec![0],
+    })),
+  ];
+  assert_eq![rows(&ingest).await?, ex_rows];
+  assert_eq![ingest.verify(false).await?, vec![]];
+
+  // running it again replaces the rows
+  ingest.coastline().await?;
+  assert_eq![rows(&ingest).await?, ex_rows];
+
+  // without bounds, open coastlines can't be closed
+  let nodes = vec![(1,(0.0,0.0)),(2,(1.0,1.0))].into_iter().collect();
+  let (areas,problems) = coastline::assemble(&[vec![1,2]], &nodes, None);
+  assert_eq![areas, vec![]];
+  assert_eq![problems.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec![
+    "invalid coastline: not closed between nodes 1 and 2".to_string(),
+  ]];
+
+  // nodes without a position are reported and the ring is built without them
+  let nodes = vec![(1,(0.0,0.0)),(2,(1.0,0.0)),(3,(1.0,1.0)),(4,(f64::NAN,0.5)),(6,(0.0,1.0))]
+    .into_iter().collect();
+  let (areas,problems) = coastline::assemble(&[vec![1,2,3,4,5,6,1]], &nodes, None);
+  assert_eq![areas, vec![coastline::Area {
+    land: true,
+    outer: vec![(0.0,0.0),(1.0,0.0),(1.0,1.0),(0.0,1.0),(0.0,0.0)],
+    holes: vec![],
+  }]];
+  assert_eq![problems.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec![
+    "invalid coastline: node 4 has no position".to_string(),
+    "invalid coastline: node 5 has no position".to_string(),
+  ]];
+  Ok(())
+}
+
+#[async_std::test]
+async fn antimeridian() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("peermaps-ingest").tempdir()?;
+  let mut xml_file = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  xml_file.push("tests/data/0/antimeridian.xml");
+
+  let mut ingest = setup(dir.path(), "ingest").await?;
+  ingest.load_xml(std::io::BufReader::new(std::fs::File::open(&xml_file)?)).await?;
+  ingest.process().await;
+
+  async fn rows(ingest: &Ingest) -> Result<Vec<((C<f32>,C<f32>),Feature)>,Error> {
+    let mut estore = ingest.estore.lock().await;
+    let mut stream = estore.db.query(&((-180.0,-90.0),(180.0,90.0))).await?;
+    let mut results = vec![];
+    while let Some(result) = stream.next().await {
+      let (pt,v) = result?;
+      if eyros::Value::get_id(&v) == 950*3+1 {
+        results.push((pt,georender_pack::decode(&v.data)?));
+      }
+    }
+    results.sort_by_key(|(pt,_)| format!["{:?}",pt]);
+    Ok(results)
+  }
+  fn line(id: u64, positions: Vec<f32>) -> Feature {
+    Feature::Line(Line {
+      id: id*3+1,
+      feature_type: get_type("highway.primary"),
+      positions,
+      labels: vec![0],
+    })
+  }
+  // the way crosses the antimeridian, so it has a row on each side
+  let crossing = vec![179.5, -16.5, -179.75, -16.75, 179.75, -17.0];
+  let ex_rows = vec![
+    ((C::Interval(-180.0,-179.75),C::Interval(-17.0,-16.5)), line(950, crossing.clone())),
+    ((C::Interval(179.5,180.0),C::Interval(-17.0,-16.5)), line(950, crossing.clone())),
+  ];
+  assert_eq![rows(&ingest).await?, ex_rows];
+  assert_eq![ingest.verify(false).await?, vec![]];
+
+  // a wide way whose segment doesn't cross spans the longitudes in between
+  {
+    let mut estore = ingest.estore.lock().await;
+    let mut stream = estore.db.query(&((-1.0,-90.0),(1.0,90.0))).await?;
+    let mut results = vec![];
+    while let Some(result) = stream.next().await {
+      let (pt,v) = result?;
+      results.push((pt,georender_pack::decode(&v.data)?));
+    }
+    assert_eq![results, vec![
+      ((C::Interval(-100.0,100.0),C::Interval(10.0,10.5)),
+        line(951, vec![-100.0, 10.0, 100.0, 10.5])),
+    ]];
+  }
+
+  // moving the node across brings it back to one row
+  ingest.changeset_osc(std::io::Cursor::new(r#"<osmChange version="0.6">
+    <modify>
+      <node id="9301" lon="179.875" lat="-16.750" />
+    </modify>
+  </osmChange>"#.as_bytes())).await?;
+  assert_eq![rows(&ingest).await?, vec![
+    ((C::Interval(179.5,179.875),C::Interval(-17.0,-16.5)),
+      line(950, vec![179.5, -16.5, 179.875, -16.75, 179.75, -17.0])),
+  ]];
+  assert_eq![ingest.verify(false).await?, vec![]];
+
+  // and moving it back splits it again
+  ingest.changeset_osc(std::io::Cursor::new(r#"<osmChange version="0.6">
+    <modify>
+      <node id="9301" lon="-179.750" lat="-16.750" />
+    </modify>
+  </osmChange>"#.as_bytes())).await?;
+  assert_eq![rows(&ingest).await?, ex_rows];
+  assert_eq![ingest.verify(false).await?, vec![]];
+
+  // deleting the way removes both rows
+  ingest.changeset_osc(std::io::Cursor::new(r#"<osmChange version="0.6">
+    <delete>
+      <way id="950" />
+    </delete>
+  </osmChange>"#.as_bytes())).await?;
+  assert_eq![rows(&ingest).await?, vec![]];
+  assert_eq![ingest.verify(false).await?, vec![]];
+  Ok(())
+}
+
 fn open(path: &std::path::Path) -> Result<Database<Key>,Error> {
   let mut options = Options::new();
   options.create_if_missing = true;
@@ -411,3 +1446,29 @@ fn open(path: &std::path::Path) -> Result<Database<Key>,Error> {
 fn get_type(key: &str) -> u64 {
   *georender_pack::osm_types::get_types().get(key).unwrap()
 }
+
+// an ingest with its own level db and eyros db in dir, named so that a test
+// can compare more than one
+async fn setup(dir: &std::path::Path, name: impl std::fmt::Display) -> Result<Ingest,Error> {
+  Ok(Ingest::new(
+    LStore::new(open(&dir.join(format!["{}-ldb",name]))?),
+    EStore::new(eyros::open_from_path2(&dir.join(format!["{}-edb",name])).await?)
+  ))
+}
+
+// the rows in a bbox with their decoded features, sorted by id
+async fn query(ingest: &Ingest, bbox: &((f32,f32),(f32,f32))) -> Result<Vec<((C<f32>,C<f32>),Feature)>,Error> {
+  let mut estore = ingest.estore.lock().await;
+  let mut stream = estore.db.query(bbox).await?;
+  let mut rows = vec![];
+  while let Some(result) = stream.next().await {
+    let (pt,v) = result?;
+    rows.push((pt,georender_pack::decode(&v.data)?));
+  }
+  rows.sort_by_key(|(_,feature)| match feature {
+    Feature::Point(x) => x.id,
+    Feature::Line(x) => x.id,
+    Feature::Area(x) => x.id,
+  });
+  Ok(rows)
+}